@@ -1,39 +1,141 @@
-use nalgebra::Vector2;
 use serde::Deserialize;
 
 use crate::{
-    helpers::{Mat3, Vec3},
+    helpers::{Mat3, Vec2, Vec3},
     object::ray::Ray,
+    sampler::Sampler,
 };
 
-#[derive(Deserialize, Debug)]
+use self::{
+    animation::{AnimationArgs, ShutterArgs},
+    exposure::{Exposure, ExposureArgs},
+    lens::{Lens, LensArgs},
+    projection::Projection,
+};
+
+pub mod animation;
+pub mod exposure;
+pub mod lens;
+pub mod projection;
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct CameraArgs {
     width: usize,
     height: usize,
+    /// Horizontal field of view of a perspective camera, in radians.
+    #[serde(default = "default_angle")]
     angle_x: f64,
+    /// Vertical field of view of a perspective camera, in radians.
+    #[serde(default = "default_angle")]
     angle_y: f64,
     position: Vec3,
     up: Vec3,
     look_at: Vec3,
+    #[serde(default)]
+    projection: Projection,
+    /// Depth of field when present, a pinhole camera otherwise.
+    #[serde(default)]
+    lens: Option<LensArgs>,
+    #[serde(default)]
+    shutter: ShutterArgs,
+    /// Scales the image like a physical camera when present; it holds the radiance
+    /// unchanged otherwise.
+    #[serde(default)]
+    exposure: Option<ExposureArgs>,
+    /// Moves the camera over a sequence of frames when present, in place of `position`,
+    /// `look_at` and the field of view. Commands that render part of an image, like
+    /// `worker`, render the first frame.
+    #[serde(default)]
+    animation: Option<AnimationArgs>,
 }
 
-impl From<CameraArgs> for Camera {
-    fn from(args: CameraArgs) -> Self {
-        let position = Vec3::new(args.position.x, args.position.y, args.position.z);
-        let up = Vec3::new(args.up.x, args.up.y, args.up.z);
-        let look_at = Vec3::new(args.look_at.x, args.look_at.y, args.look_at.z);
-        Self::new(
-            args.width,
-            args.height,
-            args.angle_x,
-            args.angle_y,
+fn default_angle() -> f64 {
+    std::f64::consts::FRAC_PI_2
+}
+
+impl CameraArgs {
+    pub fn animation(&self) -> Option<&AnimationArgs> {
+        self.animation.as_ref()
+    }
+
+    pub fn exposure(&self) -> Option<&ExposureArgs> {
+        self.exposure.as_ref()
+    }
+
+    /// The camera at `frame` of the animation, which may lie between frames, or the still
+    /// camera without one.
+    pub fn camera_at(&self, frame: f64) -> Camera {
+        // Animations without keyframes, which validation refuses, leave the camera still.
+        let animation = self
+            .animation
+            .as_ref()
+            .filter(|animation| animation.has_keyframes());
+        let (position, look_at, angle_x, angle_y) = match animation {
+            Some(animation) => {
+                let pose = animation.pose(frame, self.angle_x, self.angle_y);
+                (pose.position, pose.look_at, pose.angle_x, pose.angle_y)
+            }
+            None => (self.position, self.look_at, self.angle_x, self.angle_y),
+        };
+        let mut camera = Camera::new(
+            self.width,
+            self.height,
+            angle_x,
+            angle_y,
             position,
-            up,
+            self.up,
             look_at,
         )
+        .with_projection(self.projection.clone())
+        .with_shutter(frame, self.shutter.clone());
+        camera.exposure = self.exposure.as_ref().map(|exposure| {
+            exposure.exposure(
+                self.lens
+                    .as_ref()
+                    .and_then(|lens| lens.aperture().f_number()),
+            )
+        });
+        camera.motion = animation.map(|animation| CameraMotion {
+            animation: animation.clone(),
+            up: self.up,
+            angle_x: self.angle_x,
+            angle_y: self.angle_y,
+        });
+        match &self.lens {
+            Some(lens) => camera.with_lens(lens.lens((look_at - position).norm())),
+            None => camera,
+        }
+    }
+}
+
+/// The still camera, or the first frame of an animated one.
+impl From<CameraArgs> for Camera {
+    fn from(args: CameraArgs) -> Self {
+        let frame = args
+            .animation
+            .as_ref()
+            .map(|animation| animation.frames())
+            .filter(|frames| !frames.is_empty())
+            .map_or(0, |frames| *frames.start());
+        args.camera_at(frame as f64)
     }
 }
 
+/// What an animated camera needs to move while the shutter is open.
+#[derive(Debug)]
+struct CameraMotion {
+    animation: AnimationArgs,
+    up: Vec3,
+    angle_x: f64,
+    angle_y: f64,
+}
+
+fn camera_to_world(position: &Vec3, up: &Vec3, look_at: &Vec3) -> Mat3 {
+    let forward = (look_at - position).normalize();
+    let right = forward.cross(up).normalize();
+    Mat3::from_columns(&[right, *up, forward])
+}
+
 #[derive(Debug, Default)]
 pub struct Camera {
     position: Vec3,
@@ -42,6 +144,13 @@ pub struct Camera {
     angle_w: f64,
     angle_h: f64,
     camera_to_world: Mat3,
+    projection: Projection,
+    lens: Option<Lens>,
+    /// Frame the position and orientation above belong to.
+    time: f64,
+    shutter: ShutterArgs,
+    motion: Option<CameraMotion>,
+    exposure: Option<Exposure>,
 }
 
 impl Camera {
@@ -54,11 +163,9 @@ impl Camera {
         up: Vec3,
         look_at: Vec3,
     ) -> Self {
-        let forward = (look_at - position).normalize();
         let angle_w = (angle_x / 2.0).tan();
         let angle_h = (angle_y / 2.0).tan();
-        let right = forward.cross(&up).normalize();
-        let camera_to_world = Mat3::from_columns(&[right, up, forward]);
+        let camera_to_world = camera_to_world(&position, &up, &look_at);
 
         Self {
             position,
@@ -67,22 +174,118 @@ impl Camera {
             angle_w,
             angle_h,
             camera_to_world,
+            projection: Projection::default(),
+            lens: None,
+            time: 0.0,
+            shutter: ShutterArgs::default(),
+            motion: None,
+            exposure: None,
         }
     }
 
-    pub fn get_ray(&self, x: usize, y: usize, jitter: &Vector2<f64>) -> Ray {
+    /// Places the camera at frame `time`, with rays spread over `shutter` around it.
+    pub fn with_shutter(mut self, time: f64, shutter: ShutterArgs) -> Self {
+        self.time = time;
+        self.shutter = shutter;
+        self
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_lens(mut self, lens: Lens) -> Self {
+        self.lens = Some(lens);
+        self
+    }
+
+    /// Ray through a random point of pixel `(x, y)`, and of the lens and shutter interval
+    /// if they are open, or `None` where the projection does not cover the pixel. The lens
+    /// and then the time take the dimensions after the pixel position, so renders without
+    /// them are unchanged.
+    pub fn get_ray(&self, x: usize, y: usize, sampler: &mut impl Sampler) -> Option<Ray> {
+        let screen = self.screen(x, y, &sampler.get_pixel_2d());
+        let lens_sample = self.lens.as_ref().map(|_| sampler.get_2d());
+        let ShutterArgs { open, close } = self.shutter;
+        let time = self.time
+            + if close > open {
+                open + sampler.get_1d() * (close - open)
+            } else {
+                open
+            };
+        let (position, camera_to_world, field_of_view) = self.view_at(time);
+
+        let aspect_ratio = self.width as f64 / self.height as f64;
+        let (origin, direction) = self.projection.ray(&screen, &field_of_view, aspect_ratio)?;
+
+        let (Some(lens), Some(lens_sample)) = (&self.lens, lens_sample) else {
+            return Some(
+                Ray::new(
+                    &(position + camera_to_world * origin),
+                    &(camera_to_world * direction.normalize()),
+                )
+                .with_time(time),
+            );
+        };
+        // Every ray through the lens meets the pinhole ray where it has traveled
+        // `focus_distance` along the view direction, or along itself for panoramic
+        // projections.
+        let lens_point = lens.sample(&lens_sample);
+        let lens_point = origin + Vec3::new(lens_point.x, lens_point.y, 0.0);
+        let focus_point = origin + direction * lens.focus_distance;
+        Some(
+            Ray::new(
+                &(position + camera_to_world * lens_point),
+                &(camera_to_world * (focus_point - lens_point).normalize()),
+            )
+            .with_time(time),
+        )
+    }
+
+    /// Point `offset` into pixel `(x, y)`, from -1 to 1 across the image and upward.
+    fn screen(&self, x: usize, y: usize, offset: &Vec2) -> Vec2 {
         let xf = x as f64;
         let yf = y as f64;
-        let xs = (2.0 * (xf + jitter.x) / self.width as f64) - 1.0;
-        let ys = (2.0 * ((self.height as f64 - yf - 1.0) + jitter.y) / self.height as f64) - 1.0;
+        let xs = (2.0 * (xf + offset.x) / self.width as f64) - 1.0;
+        let ys = (2.0 * ((self.height as f64 - yf - 1.0) + offset.y) / self.height as f64) - 1.0;
+        Vec2::new(xs, ys)
+    }
 
-        let xc = xs * self.angle_w;
-        let yc = ys * self.angle_h;
+    /// Fraction of light reaching the center of pixel `(x, y)` of a perspective camera:
+    /// the fourth power of the cosine of the angle to the view direction. Other
+    /// projections have no single image plane to fall off across.
+    pub fn vignetting(&self, x: usize, y: usize) -> f64 {
+        let Projection::Perspective = self.projection else {
+            return 1.0;
+        };
+        let screen = self.screen(x, y, &Vec2::repeat(0.5));
+        let direction = Vec3::new(screen.x * self.angle_w, screen.y * self.angle_h, 1.0);
+        // The direction has a z of one, so the cosine is the inverse of its length.
+        direction.norm_squared().powi(-2)
+    }
 
-        Ray::new(
-            &self.position,
-            &(self.camera_to_world * Vec3::new(xc, yc, 1.0).normalize()),
-        )
+    pub fn exposure(&self) -> Option<&Exposure> {
+        self.exposure.as_ref()
+    }
+
+    /// Position, orientation and perspective field of view at `time`.
+    fn view_at(&self, time: f64) -> (Vec3, Mat3, Vec2) {
+        match &self.motion {
+            Some(motion) if time != self.time => {
+                let pose = motion.animation.pose(time, motion.angle_x, motion.angle_y);
+                (
+                    pose.position,
+                    camera_to_world(&pose.position, &motion.up, &pose.look_at),
+                    Vec2::new((pose.angle_x / 2.0).tan(), (pose.angle_y / 2.0).tan()),
+                )
+            }
+            _ => (
+                self.position,
+                self.camera_to_world,
+                Vec2::new(self.angle_w, self.angle_h),
+            ),
+        }
     }
 
     pub fn width(&self) -> usize {
@@ -102,3 +305,103 @@ impl Camera {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Always the center of the pixel, with random lens positions.
+    struct CenterSampler(fastrand::Rng);
+
+    impl Sampler for CenterSampler {
+        fn start_pixel_sample(&mut self, _: usize, _: usize, _: usize) {}
+
+        fn get_1d(&mut self) -> f64 {
+            self.0.f64()
+        }
+
+        fn get_2d(&mut self) -> Vec2 {
+            Vec2::new(self.0.f64(), self.0.f64())
+        }
+
+        fn get_pixel_2d(&mut self) -> Vec2 {
+            Vec2::repeat(0.5)
+        }
+    }
+
+    #[test]
+    fn lens_rays_meet_on_the_focus_plane() {
+        let lens: LensArgs =
+            serde_json::from_str(r#"{"aperture": {"type": "Radius", "radius": 0.5}, "blades": 5}"#)
+                .unwrap();
+        let position = Vec3::new(1.0, 2.0, 3.0);
+        let look_at = Vec3::new(1.0, 2.0, 9.0);
+        let pinhole = Camera::new(8, 8, 1.0, 1.0, position, Vec3::y(), look_at);
+        let camera = Camera::new(8, 8, 1.0, 1.0, position, Vec3::y(), look_at)
+            .with_lens(lens.lens((look_at - position).norm()));
+
+        let mut sampler = CenterSampler(fastrand::Rng::with_seed(3));
+        let focus = |ray: &Ray| {
+            let t = (look_at.z - ray.origin().z) / ray.direction().z;
+            ray.origin() + ray.direction() * t
+        };
+        let sharp = focus(&pinhole.get_ray(1, 6, &mut sampler).unwrap());
+
+        let mut origins = Vec::new();
+        for _ in 0..16 {
+            let ray = camera.get_ray(1, 6, &mut sampler).unwrap();
+            assert!((focus(&ray) - sharp).norm() < 1e-9);
+            assert!((ray.origin() - position).norm() <= 0.5 + 1e-12);
+            origins.push(*ray.origin());
+        }
+        assert!(origins.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel_across_the_view() {
+        let projection: Projection = serde_json::from_str(
+            r#"{"type": "Orthographic", "view_width": 4.0, "view_height": 2.0}"#,
+        )
+        .unwrap();
+        let position = Vec3::new(1.0, 2.0, 3.0);
+        let camera = Camera::new(
+            8,
+            4,
+            1.0,
+            1.0,
+            position,
+            Vec3::y(),
+            Vec3::new(1.0, 2.0, 9.0),
+        )
+        .with_projection(projection);
+
+        let mut sampler = CenterSampler(fastrand::Rng::with_seed(3));
+        let corner = camera.get_ray(0, 0, &mut sampler).unwrap();
+        let opposite = camera.get_ray(7, 3, &mut sampler).unwrap();
+        assert_eq!(corner.direction(), &Vec3::z());
+        assert_eq!(opposite.direction(), &Vec3::z());
+        // Pixel centers are half a pixel in from the edges of the 4 by 2 view.
+        let span = opposite.origin() - corner.origin();
+        assert!((span - Vec3::new(-3.5, -1.5, 0.0)).norm() < 1e-12);
+        assert!(((corner.origin() + opposite.origin()) / 2.0 - position).norm() < 1e-12);
+    }
+
+    #[test]
+    fn animations_without_keyframes_leave_the_camera_still() {
+        let still = r#"{"position": [1, 2, 3], "look_at": [1, 2, 9], "up": [0, 1, 0],
+                        "width": 4, "height": 4"#;
+        let args: CameraArgs =
+            serde_json::from_str(&format!(r#"{still}, "animation": {{"keyframes": []}}}}"#))
+                .unwrap();
+        let animation = args.animation().unwrap();
+        assert!(animation.frames().is_empty());
+        assert!(!animation.is_valid());
+
+        let still: CameraArgs = serde_json::from_str(&format!("{still}}}")).unwrap();
+        let mut sampler = CenterSampler(fastrand::Rng::with_seed(3));
+        let ray = Camera::from(args).get_ray(1, 2, &mut sampler).unwrap();
+        let expected = Camera::from(still).get_ray(1, 2, &mut sampler).unwrap();
+        assert_eq!(ray.origin(), expected.origin());
+        assert_eq!(ray.direction(), expected.direction());
+    }
+}
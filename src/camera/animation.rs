@@ -0,0 +1,110 @@
+use std::ops::{Add, Mul, RangeInclusive, Sub};
+
+use serde::Deserialize;
+
+use crate::{
+    helpers::Vec3,
+    keyframes::{self, Interpolation},
+};
+
+/// Where the camera is and looks at one frame of an animation. The field of view is the
+/// one of the camera when left out.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CameraKeyframe {
+    frame: f64,
+    position: Vec3,
+    look_at: Vec3,
+    #[serde(default)]
+    angle_x: Option<f64>,
+    #[serde(default)]
+    angle_y: Option<f64>,
+}
+
+/// Camera moving through `keyframes`, which must be in increasing frame order. Frames
+/// `first_frame` to `last_frame` are rendered, by default those of the first and last
+/// keyframe.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnimationArgs {
+    keyframes: Vec<CameraKeyframe>,
+    #[serde(default)]
+    interpolation: Interpolation,
+    #[serde(default)]
+    first_frame: Option<usize>,
+    #[serde(default)]
+    last_frame: Option<usize>,
+}
+
+/// Part of every frame over which the shutter is open, in frames from the time of the
+/// frame. Rays are spread over it, so that whatever moves meanwhile is blurred; the shutter
+/// opens and closes at once by default, without blur.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ShutterArgs {
+    #[serde(default)]
+    pub open: f64,
+    #[serde(default)]
+    pub close: f64,
+}
+
+/// Camera settings that are interpolated at one point in time.
+pub struct CameraPose {
+    pub position: Vec3,
+    pub look_at: Vec3,
+    pub angle_x: f64,
+    pub angle_y: f64,
+}
+
+impl AnimationArgs {
+    /// Whether there are keyframes, in order, and at least one frame to render.
+    pub fn is_valid(&self) -> bool {
+        self.has_keyframes()
+            && self
+                .keyframes
+                .windows(2)
+                .all(|pair| pair[0].frame < pair[1].frame)
+            && !self.frames().is_empty()
+    }
+
+    /// Whether there are poses to take, which [`Self::pose`] needs.
+    pub fn has_keyframes(&self) -> bool {
+        !self.keyframes.is_empty()
+    }
+
+    /// Frames to render, none without keyframes.
+    pub fn frames(&self) -> RangeInclusive<usize> {
+        let (Some(first_keyframe), Some(last_keyframe)) =
+            (self.keyframes.first(), self.keyframes.last())
+        else {
+            return RangeInclusive::new(1, 0);
+        };
+        let first = self
+            .first_frame
+            .unwrap_or(first_keyframe.frame.ceil() as usize);
+        let last = self
+            .last_frame
+            .unwrap_or(last_keyframe.frame.floor() as usize);
+        first..=last
+    }
+
+    /// Pose at `frame`, which may lie between frames, with `angle_x` and `angle_y` for
+    /// keyframes that leave out the field of view.
+    pub fn pose(&self, frame: f64, angle_x: f64, angle_y: f64) -> CameraPose {
+        CameraPose {
+            position: self.track(frame, |keyframe| keyframe.position),
+            look_at: self.track(frame, |keyframe| keyframe.look_at),
+            angle_x: self.track(frame, |keyframe| keyframe.angle_x.unwrap_or(angle_x)),
+            angle_y: self.track(frame, |keyframe| keyframe.angle_y.unwrap_or(angle_y)),
+        }
+    }
+
+    fn track<T>(&self, frame: f64, value: impl Fn(&CameraKeyframe) -> T) -> T
+    where
+        T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+    {
+        keyframes::interpolate(
+            self.interpolation,
+            &self.keyframes,
+            |keyframe| (keyframe.frame, value(keyframe)),
+            frame,
+        )
+    }
+}
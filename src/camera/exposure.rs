@@ -0,0 +1,130 @@
+use serde::Deserialize;
+
+use crate::{helpers::gray_scale, image::Image};
+
+/// Luminance the log-average of the image is mapped to by auto-exposure.
+const MIDDLE_GRAY: f64 = 0.18;
+
+/// Settings of a physical camera, which scale the radiance reaching the sensor, taken in
+/// candela per square meter, into image values the way a photographic exposure does.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExposureArgs {
+    #[serde(default = "default_iso")]
+    iso: f64,
+    /// Seconds the sensor collects light. Unrelated to the `shutter` interval of motion
+    /// blur, which is in frames.
+    #[serde(default = "default_shutter_time")]
+    shutter_time: f64,
+    /// The f-number of the lens aperture when it is given as one, 2.8 otherwise.
+    #[serde(default)]
+    f_number: Option<f64>,
+    /// Darkens the image toward its edges by the fourth power of the cosine of the angle to
+    /// the view direction, as light reaches the sensor there at a slant.
+    #[serde(default)]
+    vignetting: bool,
+    /// Exposes the image so that its log-average luminance becomes middle gray, in place
+    /// of ISO, shutter time and f-number. Crop windows cannot use it, as they would be
+    /// exposed by their own pixels rather than those of the whole image.
+    #[serde(default)]
+    auto_exposure: bool,
+}
+
+fn default_iso() -> f64 {
+    100.0
+}
+
+fn default_shutter_time() -> f64 {
+    1.0 / 60.0
+}
+
+fn default_f_number() -> f64 {
+    2.8
+}
+
+impl ExposureArgs {
+    pub fn auto_exposure(&self) -> bool {
+        self.auto_exposure
+    }
+
+    pub fn exposure(&self, lens_f_number: Option<f64>) -> Exposure {
+        let f_number = self
+            .f_number
+            .or(lens_f_number)
+            .unwrap_or_else(default_f_number);
+        Exposure {
+            // Saturation-based sensitivity: the luminance that saturates the sensor is
+            // 78 / (0.65 ISO) N² / t, and is mapped to one.
+            scale: self.shutter_time * self.iso / (120.0 * f_number * f_number),
+            vignetting: self.vignetting,
+            auto_exposure: self.auto_exposure,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Exposure {
+    scale: f64,
+    pub vignetting: bool,
+    auto_exposure: bool,
+}
+
+impl Exposure {
+    /// Factor every pixel of `image` is multiplied by, given the cos⁴ falloff of every
+    /// pixel in `vignetting` when it is enabled.
+    pub fn gains(&self, image: &Image, vignetting: Option<Vec<f64>>) -> Vec<f64> {
+        let mut gains = vignetting.unwrap_or_else(|| vec![1.0; image.pixels().len()]);
+        let scale = if self.auto_exposure {
+            // Pixels that see nothing, like the background, are left out, as their
+            // logarithm would outweigh everything else.
+            let (log_sum, count) = image
+                .pixels()
+                .iter()
+                .zip(&gains)
+                .map(|(pixel, gain)| gray_scale(pixel) * gain)
+                .filter(|luminance| *luminance > 0.0)
+                .fold((0.0, 0), |(sum, count), luminance| {
+                    (sum + luminance.ln(), count + 1)
+                });
+            if count == 0 {
+                1.0
+            } else {
+                MIDDLE_GRAY / (log_sum / count as f64).exp()
+            }
+        } else {
+            self.scale
+        };
+        for gain in &mut gains {
+            *gain *= scale;
+        }
+        gains
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::helpers::Color;
+
+    use super::*;
+
+    #[test]
+    fn exposure_follows_camera_settings_or_the_image() {
+        let args = |json| serde_json::from_str::<ExposureArgs>(json).unwrap();
+        let image = Image::new(2, 1, vec![Color::repeat(0.5), Color::repeat(2.0)]);
+
+        // Sunny 16: f/16 at 1/100 s and ISO 100 saturates at 30720 cd/m².
+        let sunny = args(r#"{"shutter_time": 0.01, "f_number": 16}"#).exposure(None);
+        let gain = sunny.gains(&image, None)[0];
+        assert!((1.0 / gain - 30720.0).abs() < 1e-6);
+        // Doubling ISO makes up for halving the shutter time; the lens f-number is the
+        // fallback.
+        let faster = args(r#"{"iso": 200, "shutter_time": 0.005}"#).exposure(Some(16.0));
+        assert!((faster.gains(&image, None)[0] - gain).abs() < 1e-12);
+
+        // After the falloff the luminances are 0.5 and 1, whose log-average of √0.5 becomes
+        // middle gray.
+        let auto = args(r#"{"auto_exposure": true, "vignetting": true}"#).exposure(None);
+        let gains = auto.gains(&image, Some(vec![1.0, 0.5]));
+        assert!((gains[0] - MIDDLE_GRAY / 0.5_f64.sqrt()).abs() < 1e-12);
+        assert!((gains[1] - gains[0] * 0.5).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,163 @@
+use std::f64::consts::PI;
+
+use serde::Deserialize;
+
+use crate::helpers::Vec2;
+
+/// Size of the opening of the lens.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum Aperture {
+    /// Radius in scene units.
+    Radius { radius: f64 },
+    /// Focal length over aperture diameter, with the focal length in scene units.
+    FNumber { f_number: f64, focal_length: f64 },
+}
+
+impl Aperture {
+    pub fn f_number(&self) -> Option<f64> {
+        match self {
+            Self::Radius { .. } => None,
+            Self::FNumber { f_number, .. } => Some(*f_number),
+        }
+    }
+
+    pub fn radius(&self) -> f64 {
+        match self {
+            Self::Radius { radius } => *radius,
+            Self::FNumber {
+                f_number,
+                focal_length,
+            } => focal_length / (2.0 * f_number),
+        }
+    }
+}
+
+/// Thin lens in front of the camera: points on the focus plane stay sharp, everything
+/// nearer or farther is blurred over the shape of the aperture.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LensArgs {
+    aperture: Aperture,
+    /// Distance along the view direction of the plane in focus; the distance to `look_at`
+    /// by default.
+    #[serde(default)]
+    focus_distance: Option<f64>,
+    /// Number of aperture blades, which make out-of-focus highlights polygonal; a round
+    /// aperture when absent.
+    #[serde(default)]
+    blades: Option<u32>,
+    /// Rotation of the blades in degrees.
+    #[serde(default)]
+    blade_rotation: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lens {
+    pub radius: f64,
+    pub focus_distance: f64,
+    blades: Option<u32>,
+    blade_rotation: f64,
+}
+
+impl LensArgs {
+    pub fn aperture(&self) -> &Aperture {
+        &self.aperture
+    }
+
+    pub fn lens(&self, default_focus_distance: f64) -> Lens {
+        Lens {
+            radius: self.aperture.radius(),
+            focus_distance: self.focus_distance.unwrap_or(default_focus_distance),
+            blades: self.blades.filter(|blades| *blades >= 3),
+            blade_rotation: self.blade_rotation.to_radians(),
+        }
+    }
+}
+
+impl Lens {
+    /// Point on the aperture, uniformly distributed over its area, from a uniform sample
+    /// of the unit square.
+    pub fn sample(&self, u: &Vec2) -> Vec2 {
+        let point = match self.blades {
+            Some(blades) => sample_polygon(u, blades, self.blade_rotation),
+            None => sample_disk(u),
+        };
+        point * self.radius
+    }
+}
+
+/// Concentric mapping of the square onto the unit disk (Shirley and Chiu 1997), which
+/// keeps strata of the sampler compact.
+fn sample_disk(u: &Vec2) -> Vec2 {
+    let offset = u * 2.0 - Vec2::repeat(1.0);
+    if offset.x == 0.0 && offset.y == 0.0 {
+        return Vec2::zeros();
+    }
+
+    let (radius, angle) = if offset.x.abs() > offset.y.abs() {
+        (offset.x, PI / 4.0 * (offset.y / offset.x))
+    } else {
+        (offset.y, PI / 2.0 - PI / 4.0 * (offset.x / offset.y))
+    };
+    Vec2::new(angle.cos(), angle.sin()) * radius
+}
+
+/// Uniform point in the regular polygon with `blades` corners on the unit circle: `u.x`
+/// selects the triangle between the center and one edge, and is reused inside it.
+fn sample_polygon(u: &Vec2, blades: u32, rotation: f64) -> Vec2 {
+    let scaled = u.x * blades as f64;
+    let edge = scaled.floor().min(blades as f64 - 1.0);
+    let corner = |index: f64| {
+        let angle = rotation + 2.0 * PI * index / blades as f64;
+        Vec2::new(angle.cos(), angle.sin())
+    };
+
+    let along_radius = (scaled - edge).sqrt();
+    (corner(edge) * (1.0 - u.y) + corner(edge + 1.0) * u.y) * along_radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aperture_samples_cover_its_shape() {
+        let mut rng = fastrand::Rng::with_seed(1);
+        let hexagon = LensArgs {
+            aperture: Aperture::FNumber {
+                f_number: 2.0,
+                focal_length: 2.0,
+            },
+            focus_distance: None,
+            blades: Some(6),
+            blade_rotation: 0.0,
+        }
+        .lens(1.0);
+        let disk = Lens {
+            blades: None,
+            ..hexagon.clone()
+        };
+
+        // Fraction of the area within some distance of the center: half of a disk lies
+        // within 1 / sqrt(2) of its radius, and the circle inscribed in a hexagon covers
+        // pi / (2 sqrt(3)) of it.
+        let inscribed_hexagon = PI / (2.0 * 3f64.sqrt());
+        for (lens, distance, fraction) in [
+            (hexagon, 0.5 * (PI / 6.0).cos(), inscribed_hexagon),
+            (disk, 0.5 / 2f64.sqrt(), 0.5),
+        ] {
+            let points: Vec<_> = (0..10_000)
+                .map(|_| lens.sample(&Vec2::new(rng.f64(), rng.f64())))
+                .collect();
+            let mean = points.iter().sum::<Vec2>() / points.len() as f64;
+            assert!(mean.norm() < 0.02, "{mean}");
+
+            assert!(points.iter().all(|point| point.norm() <= 0.5 + 1e-12));
+            let inner = points
+                .iter()
+                .filter(|point| point.norm() < distance)
+                .count();
+            assert!((inner as f64 / points.len() as f64 - fraction).abs() < 0.02);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use serde::Deserialize;
+
+use crate::helpers::{Vec2, Vec3};
+
+/// How points of the image map to rays leaving the camera.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type")]
+pub enum Projection {
+    /// Rays from the camera position, spread over `angle_x` by `angle_y`.
+    #[default]
+    Perspective,
+    /// Parallel rays along the view direction, from a plane of `view_width` by
+    /// `view_height` scene units centered on the camera position.
+    Orthographic { view_width: f64, view_height: f64 },
+    /// Full sphere with longitude across and latitude down the image, which should be
+    /// twice as wide as it is high. The view direction is at the center.
+    Equirectangular,
+    /// Six square faces of 90 degrees side by side, in the order right, left, up, down,
+    /// front and back, so the image should be six times as wide as it is high. The up and
+    /// down faces have the front direction toward the bottom and top of the image.
+    CubeMap,
+    /// Image circle touching the shorter edges of the image, with the view direction at its
+    /// center and `field_of_view` radians across. Pixels outside the circle show the
+    /// background.
+    Fisheye {
+        #[serde(default)]
+        mapping: FisheyeMapping,
+        #[serde(default = "default_fisheye_field_of_view")]
+        field_of_view: f64,
+    },
+}
+
+/// How the distance from the center of a fisheye image grows with the angle to the view
+/// direction.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum FisheyeMapping {
+    /// Proportional to the angle, as on most dome masters.
+    #[default]
+    Equidistant,
+    /// Proportional to the chord of the angle, so every pixel covers the same solid angle.
+    Equisolid,
+}
+
+fn default_fisheye_field_of_view() -> f64 {
+    PI
+}
+
+impl Projection {
+    /// Origin and direction in camera space of the ray through `screen`, which runs from
+    /// -1 to 1 across the image, or `None` outside of the projection. `field_of_view`
+    /// holds the tangents of the half angles of a perspective camera, and `aspect_ratio`
+    /// is the width of the image over its height.
+    ///
+    /// Perspective and orthographic directions have a `z` of 1, so they reach a plane
+    /// parallel to the image at the distance along the view direction. Panoramic ones are
+    /// normalized instead, and reach a sphere around the camera.
+    pub fn ray(
+        &self,
+        screen: &Vec2,
+        field_of_view: &Vec2,
+        aspect_ratio: f64,
+    ) -> Option<(Vec3, Vec3)> {
+        let direction = match self {
+            Self::Perspective => {
+                Vec3::new(screen.x * field_of_view.x, screen.y * field_of_view.y, 1.0)
+            }
+            Self::Orthographic {
+                view_width,
+                view_height,
+            } => {
+                let origin = Vec3::new(
+                    screen.x * view_width / 2.0,
+                    screen.y * view_height / 2.0,
+                    0.0,
+                );
+                return Some((origin, Vec3::z()));
+            }
+            Self::Equirectangular => {
+                let longitude = screen.x * PI;
+                let latitude = screen.y * FRAC_PI_2;
+                Vec3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    latitude.cos() * longitude.cos(),
+                )
+            }
+            Self::CubeMap => {
+                let position = (screen.x + 1.0) * 3.0;
+                let face = (position as usize).min(5);
+                let u = (position - face as f64) * 2.0 - 1.0;
+                let v = screen.y;
+                match face {
+                    0 => Vec3::new(1.0, v, -u),
+                    1 => Vec3::new(-1.0, v, u),
+                    2 => Vec3::new(u, 1.0, -v),
+                    3 => Vec3::new(u, -1.0, v),
+                    4 => Vec3::new(u, v, 1.0),
+                    _ => Vec3::new(-u, v, -1.0),
+                }
+                .normalize()
+            }
+            Self::Fisheye {
+                mapping,
+                field_of_view,
+            } => {
+                // Stretch the longer side, so that the circle stays round.
+                let screen = Vec2::new(
+                    screen.x * aspect_ratio.max(1.0),
+                    screen.y / aspect_ratio.min(1.0),
+                );
+                let radius = screen.norm();
+                if radius > 1.0 {
+                    return None;
+                }
+                let angle = match mapping {
+                    FisheyeMapping::Equidistant => radius * field_of_view / 2.0,
+                    FisheyeMapping::Equisolid => {
+                        2.0 * (radius * (field_of_view / 4.0).sin()).asin()
+                    }
+                };
+                let towards = if radius > 0.0 {
+                    screen / radius
+                } else {
+                    Vec2::zeros()
+                };
+                Vec3::new(
+                    angle.sin() * towards.x,
+                    angle.sin() * towards.y,
+                    angle.cos(),
+                )
+            }
+        };
+        Some((Vec3::zeros(), direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direction(projection: &Projection, x: f64, y: f64) -> Vec3 {
+        let (origin, direction) = projection
+            .ray(&Vec2::new(x, y), &Vec2::repeat(1.0), 1.0)
+            .unwrap();
+        assert_eq!(origin, Vec3::zeros());
+        direction
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).norm() < 1e-12, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn panoramic_directions_follow_their_angles() {
+        let equirectangular = Projection::Equirectangular;
+        assert_close(direction(&equirectangular, 0.0, 0.0), Vec3::z());
+        assert_close(direction(&equirectangular, 0.5, 0.0), Vec3::x());
+        assert_close(direction(&equirectangular, -1.0, 0.0), -Vec3::z());
+        assert_close(direction(&equirectangular, 0.3, 1.0), Vec3::y());
+
+        // Centers of the six faces, then the edge where the front face meets the right one.
+        let cube_map = Projection::CubeMap;
+        let centers = [
+            Vec3::x(),
+            -Vec3::x(),
+            Vec3::y(),
+            -Vec3::y(),
+            Vec3::z(),
+            -Vec3::z(),
+        ];
+        for (face, center) in centers.into_iter().enumerate() {
+            let x = (face as f64 + 0.5) / 3.0 - 1.0;
+            assert_close(direction(&cube_map, x, 0.0), center);
+        }
+        let front_edge = direction(&cube_map, 2.0 / 3.0 - 1e-12, 0.0);
+        assert!((front_edge - direction(&cube_map, -1.0, 0.0)).norm() < 1e-9);
+
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let fisheye = Projection::Fisheye {
+                mapping,
+                field_of_view: PI,
+            };
+            assert_close(direction(&fisheye, 0.0, 0.0), Vec3::z());
+            assert_close(direction(&fisheye, 0.0, -1.0), -Vec3::y());
+            assert!(fisheye
+                .ray(&Vec2::new(0.8, 0.8), &Vec2::repeat(1.0), 1.0)
+                .is_none());
+        }
+        // On wide and tall images the circle touches the shorter edges only.
+        let fisheye = Projection::Fisheye {
+            mapping: FisheyeMapping::Equidistant,
+            field_of_view: PI,
+        };
+        let wide = |x, y| fisheye.ray(&Vec2::new(x, y), &Vec2::repeat(1.0), 2.0);
+        assert_close(wide(0.5, 0.0).unwrap().1, Vec3::x());
+        assert_close(wide(0.0, 1.0).unwrap().1, Vec3::y());
+        assert!(wide(0.75, 0.0).is_none());
+        let tall = |x, y| fisheye.ray(&Vec2::new(x, y), &Vec2::repeat(1.0), 0.5);
+        assert_close(tall(1.0, 0.0).unwrap().1, Vec3::x());
+        assert_close(tall(0.0, -0.5).unwrap().1, -Vec3::y());
+        assert!(tall(0.0, 0.75).is_none());
+        // Half the radius is half the angle from the view direction with equidistant
+        // mapping, and a quarter of the hemisphere's solid angle with equisolid mapping.
+        let halfway = |mapping| {
+            let fisheye = Projection::Fisheye {
+                mapping,
+                field_of_view: PI,
+            };
+            direction(&fisheye, 0.5, 0.0).z
+        };
+        assert!((halfway(FisheyeMapping::Equidistant) - (PI / 4.0).cos()).abs() < 1e-12);
+        assert!((halfway(FisheyeMapping::Equisolid) - 0.75).abs() < 1e-12);
+    }
+}
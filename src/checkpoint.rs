@@ -0,0 +1,183 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Write},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    film::{Aov, FilmPixel},
+    renderer::RenderState,
+};
+
+const MAGIC: &[u8; 8] = b"RTCKPT01";
+
+/// Longest AOV name a header may hold, so that corrupt files and misbehaving peers cannot
+/// make readers allocate without bounds.
+const MAX_AOV_NAME_LENGTH: usize = 64;
+
+/// Where the accumulated samples are saved, every `interval` seconds and once more when
+/// the render finishes.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckpointArgs {
+    pub file: String,
+    #[serde(default = "default_interval")]
+    pub interval: f64,
+}
+
+fn default_interval() -> f64 {
+    60.0
+}
+
+/// What the samples of a checkpoint were rendered from. The random streams are seeded by
+/// the configuration seed and the sample index, so the sample count of the
+/// [`RenderState`] is all the sampler state there is.
+#[derive(Debug, PartialEq)]
+pub struct Checkpoint {
+    /// Hash of the scene and of every setting that changes the image.
+    pub fingerprint: u64,
+    pub width: usize,
+    pub height: usize,
+    pub aov_names: Vec<String>,
+}
+
+impl Checkpoint {
+    /// Writes to a temporary file first, so that a render killed while saving still
+    /// leaves the previous checkpoint intact.
+    pub fn save(&self, path: &str, state: &RenderState) -> std::io::Result<()> {
+        let temporary_path = format!("{path}.tmp");
+        let mut w = BufWriter::new(File::create(&temporary_path)?);
+
+        w.write_all(MAGIC)?;
+        self.write_header(&mut w)?;
+        write_u64(&mut w, state.samples as u64)?;
+        for pixel in &state.pixels {
+            pixel.write(&mut w)?;
+        }
+        w.flush()?;
+        drop(w);
+
+        std::fs::rename(temporary_path, path)
+    }
+
+    pub fn load(path: &str) -> std::io::Result<(Self, RenderState)> {
+        let mut r = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a checkpoint file"));
+        }
+
+        let checkpoint = Self::read_header(&mut r)?;
+        let samples = read_u64(&mut r)? as usize;
+        let pixel_count = checkpoint
+            .width
+            .checked_mul(checkpoint.height)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "image size out of range"))?;
+        let pixels = (0..pixel_count)
+            .map(|_| FilmPixel::read(&mut r, checkpoint.aov_names.len()))
+            .collect::<std::io::Result<_>>()?;
+
+        Ok((checkpoint, RenderState { samples, pixels }))
+    }
+
+    pub fn write_header(&self, w: &mut impl Write) -> std::io::Result<()> {
+        for value in [
+            self.fingerprint,
+            self.width as u64,
+            self.height as u64,
+            self.aov_names.len() as u64,
+        ] {
+            write_u64(w, value)?;
+        }
+        for name in &self.aov_names {
+            write_u64(w, name.len() as u64)?;
+            w.write_all(name.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_header(r: &mut impl Read) -> std::io::Result<Self> {
+        let fingerprint = read_u64(r)?;
+        let width = read_u64(r)? as usize;
+        let height = read_u64(r)? as usize;
+        let aov_count = read_u64(r)? as usize;
+        if aov_count > Aov::ALL.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "too many AOVs"));
+        }
+
+        let aov_names = (0..aov_count)
+            .map(|_| {
+                let length = read_u64(r)? as usize;
+                if length > MAX_AOV_NAME_LENGTH {
+                    return Err(Error::new(ErrorKind::InvalidData, "AOV name too long"));
+                }
+                let mut name = vec![0; length];
+                r.read_exact(&mut name)?;
+                String::from_utf8(name).map_err(|error| Error::new(ErrorKind::InvalidData, error))
+            })
+            .collect::<std::io::Result<_>>()?;
+
+        Ok(Self {
+            fingerprint,
+            width,
+            height,
+            aov_names,
+        })
+    }
+}
+
+pub fn write_u64(w: &mut impl Write, value: u64) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+pub fn write_f64(w: &mut impl Write, value: f64) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+pub fn read_u64(r: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub fn read_f64(r: &mut impl Read) -> std::io::Result<f64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+/// 64 bit FNV-1a, which unlike the standard library hasher is stable across builds.
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_refuse_oversized_fields() {
+        let header = Checkpoint {
+            fingerprint: 5,
+            width: 4,
+            height: 3,
+            aov_names: vec!["depth".to_owned()],
+        };
+        let mut bytes = Vec::new();
+        header.write_header(&mut bytes).unwrap();
+        assert_eq!(Checkpoint::read_header(&mut &bytes[..]).unwrap(), header);
+
+        let read_with = |offset: usize, value: u64| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            Checkpoint::read_header(&mut &bytes[..]).unwrap_err().kind()
+        };
+        // The AOV count, then the length of the first name.
+        assert_eq!(read_with(24, u64::MAX), ErrorKind::InvalidData);
+        assert_eq!(read_with(32, 1 << 40), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,616 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    ops::Range,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    checkpoint::{read_u64, write_u64, Checkpoint},
+    film::FilmPixel,
+    renderer::{tiles::Region, Renderer},
+};
+
+const MAGIC: &[u8; 8] = b"RTWORK01";
+const PARTIAL_MAGIC: &[u8; 8] = b"RTPART01";
+
+/// Messages of the coordinator, each followed by its fields as little endian `u64`.
+const DONE: u64 = 0;
+const ASSIGNMENT: u64 = 1;
+const REJECTED: u64 = 2;
+
+/// How long idle connections wait before looking for work again.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a new connection may take to say what it renders.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the coordinator splits a render into assignments for its workers.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DistributedArgs {
+    #[serde(default = "default_tiles_per_assignment")]
+    tiles_per_assignment: usize,
+    /// Samples per pixel of every assignment, all of them by default. Splitting the
+    /// samples lets more workers share small images.
+    #[serde(default)]
+    samples_per_assignment: Option<usize>,
+    /// Seconds a worker may take for one assignment before it is handed to another one.
+    #[serde(default = "default_assignment_timeout")]
+    assignment_timeout: f64,
+}
+
+fn default_tiles_per_assignment() -> usize {
+    16
+}
+
+fn default_assignment_timeout() -> f64 {
+    3600.0
+}
+
+impl Default for DistributedArgs {
+    fn default() -> Self {
+        Self {
+            tiles_per_assignment: default_tiles_per_assignment(),
+            samples_per_assignment: None,
+            assignment_timeout: default_assignment_timeout(),
+        }
+    }
+}
+
+/// Part of a render: the samples with indices in `samples` of `tiles`. The tiles are sent
+/// along, so that workers do not depend on their own tile settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub samples: Range<usize>,
+    pub tiles: Vec<Region>,
+}
+
+impl DistributedArgs {
+    pub fn is_valid(&self) -> bool {
+        self.assignment_timeout > 0.0 && self.assignment_timeout.is_finite()
+    }
+
+    pub fn assignment_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.assignment_timeout)
+    }
+
+    /// Assignments covering every sample of every tile. All tiles get their first samples
+    /// before any tile gets more.
+    pub fn assignments(&self, tiles: &[Region], samples_per_pixel: usize) -> Vec<Assignment> {
+        let samples_per_assignment = self
+            .samples_per_assignment
+            .unwrap_or(samples_per_pixel)
+            .max(1);
+        let tiles_per_assignment = self.tiles_per_assignment.max(1);
+
+        (0..samples_per_pixel)
+            .step_by(samples_per_assignment)
+            .flat_map(|first_sample| {
+                let samples =
+                    first_sample..(first_sample + samples_per_assignment).min(samples_per_pixel);
+                tiles
+                    .chunks(tiles_per_assignment)
+                    .map(move |tiles| Assignment {
+                        samples: samples.clone(),
+                        tiles: tiles.to_vec(),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Renders `assignment` into a buffer of the whole window, where the pixels of the other
+/// tiles have no samples.
+pub fn render_assignment(renderer: &Renderer, assignment: &Assignment) -> Vec<FilmPixel> {
+    let mut pixels = renderer.start().pixels;
+    renderer.render_tiles(&mut pixels, &assignment.tiles, assignment.samples.clone());
+    pixels
+}
+
+/// Renders the assignments of the coordinator at `address` until it has none left.
+/// `header` describes the render, so that the coordinator can refuse workers with another
+/// scene or configuration.
+pub fn work(renderer: &Renderer, header: &Checkpoint, address: &str) -> std::io::Result<()> {
+    let stream = TcpStream::connect(address)?;
+    let mut r = BufReader::new(stream.try_clone()?);
+    let mut w = BufWriter::new(stream);
+
+    w.write_all(MAGIC)?;
+    header.write_header(&mut w)?;
+    w.flush()?;
+
+    loop {
+        match read_u64(&mut r)? {
+            DONE => return Ok(()),
+            ASSIGNMENT => {}
+            REJECTED => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "the coordinator renders a different scene or configuration",
+                ))
+            }
+            message => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown message {message}"),
+                ))
+            }
+        }
+        let assignment = read_assignment(&mut r, &renderer.window())?;
+        let pixels = render_assignment(renderer, &assignment);
+        write_tile_pixels(&mut w, renderer, &assignment, &pixels)?;
+        w.flush()?;
+    }
+}
+
+/// Saves `assignment` and the pixels of its tiles out of `pixels`, as rendered by
+/// [`render_assignment`], to `path`. Keeping the assignment lets [`load_partial`] place
+/// the pixels whatever the tile settings of the merging process.
+pub fn save_partial(
+    path: &str,
+    renderer: &Renderer,
+    header: &Checkpoint,
+    assignment: &Assignment,
+    pixels: &[FilmPixel],
+) -> std::io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(PARTIAL_MAGIC)?;
+    header.write_header(&mut w)?;
+    write_assignment(&mut w, assignment)?;
+    write_tile_pixels(&mut w, renderer, assignment, pixels)?;
+    w.flush()
+}
+
+/// Loads a partial render saved by [`save_partial`]: its header, its assignment and the
+/// pixels of its tiles in order. Tiles must lie inside `window`.
+pub fn load_partial(
+    path: &str,
+    window: &Region,
+) -> std::io::Result<(Checkpoint, Assignment, Vec<FilmPixel>)> {
+    let mut r = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != PARTIAL_MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "not a partial render"));
+    }
+
+    let header = Checkpoint::read_header(&mut r)?;
+    let assignment = read_assignment(&mut r, window)?;
+    let pixels = read_tile_pixels(&mut r, &assignment, header.aov_names.len(), None)?;
+    Ok((header, assignment, pixels))
+}
+
+/// Adds the pixels of the tiles of `assignment`, in the order of [`write_tile_pixels`], to
+/// the window buffer `pixels`.
+pub fn merge_assignment(
+    renderer: &Renderer,
+    pixels: &mut [FilmPixel],
+    assignment: &Assignment,
+    tile_pixels: &[FilmPixel],
+) {
+    let positions = assignment.tiles.iter().flat_map(|tile| tile.pixels());
+    for (position, rendered) in positions.zip(tile_pixels) {
+        pixels[renderer.window().index(position)].merge(renderer.aovs(), rendered);
+    }
+}
+
+/// Checks that `assignments` render every sample below `samples_per_pixel` of every pixel
+/// of `window` exactly once.
+pub fn check_coverage(
+    window: &Region,
+    samples_per_pixel: usize,
+    assignments: &[Assignment],
+) -> std::io::Result<()> {
+    let mut ranges = vec![Vec::new(); window.width * window.height];
+    for assignment in assignments {
+        for tile in &assignment.tiles {
+            for position in tile.pixels() {
+                ranges[window.index(position)].push(assignment.samples.clone());
+            }
+        }
+    }
+
+    for (index, mut ranges) in ranges.into_iter().enumerate() {
+        let (x, y) = (
+            window.x + index % window.width,
+            window.y + index / window.width,
+        );
+        ranges.sort_by_key(|samples| samples.start);
+        let mut covered = 0;
+        for samples in ranges {
+            if samples.start != covered {
+                let problem = if samples.start < covered {
+                    "more than once"
+                } else {
+                    "by none"
+                };
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("sample {covered} of pixel ({x}, {y}) is rendered {problem}"),
+                ));
+            }
+            covered = samples.end;
+        }
+        if covered != samples_per_pixel {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("sample {covered} of pixel ({x}, {y}) is rendered by none"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Writes the pixels of the tiles of `assignment` out of the window buffer `pixels`, tile
+/// by tile and row by row.
+fn write_tile_pixels(
+    w: &mut impl Write,
+    renderer: &Renderer,
+    assignment: &Assignment,
+    pixels: &[FilmPixel],
+) -> std::io::Result<()> {
+    for tile in &assignment.tiles {
+        for position in tile.pixels() {
+            pixels[renderer.window().index(position)].write(w)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the pixels written by [`write_tile_pixels`], failing once `deadline` has passed.
+fn read_tile_pixels(
+    r: &mut impl Read,
+    assignment: &Assignment,
+    aov_count: usize,
+    deadline: Option<Instant>,
+) -> std::io::Result<Vec<FilmPixel>> {
+    let pixel_count: usize = assignment
+        .tiles
+        .iter()
+        .map(|tile| tile.width * tile.height)
+        .sum();
+    (0..pixel_count)
+        .map(|_| {
+            if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                return Err(Error::new(ErrorKind::TimedOut, "assignment timed out"));
+            }
+            FilmPixel::read(r, aov_count)
+        })
+        .collect()
+}
+
+fn write_assignment(w: &mut impl Write, assignment: &Assignment) -> std::io::Result<()> {
+    let tiles = assignment
+        .tiles
+        .iter()
+        .flat_map(|tile| [tile.x, tile.y, tile.width, tile.height]);
+    for value in [
+        assignment.samples.start,
+        assignment.samples.end,
+        assignment.tiles.len(),
+    ]
+    .into_iter()
+    .chain(tiles)
+    {
+        write_u64(w, value as u64)?;
+    }
+    Ok(())
+}
+
+/// Reads an assignment, refusing tiles outside of `window` and empty or reversed sample
+/// ranges.
+fn read_assignment(r: &mut impl Read, window: &Region) -> std::io::Result<Assignment> {
+    let mut read = || read_u64(r).map(|value| value as usize);
+    let samples = read()?..read()?;
+    let tile_count = read()?;
+    let tiles = (0..tile_count)
+        .map(|_| {
+            Ok(Region {
+                x: read()?,
+                y: read()?,
+                width: read()?,
+                height: read()?,
+            })
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    if samples.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, "empty sample range"));
+    }
+    if !tiles.iter().all(|tile| window.contains(tile)) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "tile outside of the rendered window",
+        ));
+    }
+    Ok(Assignment { samples, tiles })
+}
+
+/// Assignments still to be handed out and the pixels of the tiles of the finished ones.
+struct Progress {
+    pending: VecDeque<usize>,
+    results: Vec<Option<Vec<FilmPixel>>>,
+}
+
+impl Progress {
+    fn finished(&self) -> bool {
+        self.results.iter().all(|result| result.is_some())
+    }
+}
+
+/// Waits for workers on `listener`, hands out `assignments` and returns the pixels of the
+/// window once every assignment was rendered. The assignment of a worker that fails, or
+/// that takes longer than `timeout` for it, is handed to the next one.
+pub fn coordinate(
+    renderer: &Renderer,
+    header: &Checkpoint,
+    listener: &TcpListener,
+    assignments: &[Assignment],
+    timeout: Duration,
+) -> std::io::Result<Vec<FilmPixel>> {
+    listener.set_nonblocking(true)?;
+    let progress = Mutex::new(Progress {
+        pending: (0..assignments.len()).collect(),
+        results: vec![None; assignments.len()],
+    });
+
+    std::thread::scope(|scope| -> std::io::Result<()> {
+        while !progress.lock().unwrap().finished() {
+            let (stream, peer) = match listener.accept() {
+                Ok(connection) => connection,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            let progress = &progress;
+            scope.spawn(move || {
+                if let Err(error) = serve(header, assignments, progress, stream, timeout) {
+                    eprintln!("Worker {peer} failed: {error}");
+                }
+            });
+        }
+        Ok(())
+    })?;
+
+    // Merging in the order of the assignments rather than as they arrive keeps the sums,
+    // and so the image, independent of the workers.
+    let mut pixels = renderer.start().pixels;
+    let results = progress.into_inner().unwrap().results;
+    for (assignment, tile_pixels) in assignments.iter().zip(results.into_iter().flatten()) {
+        merge_assignment(renderer, &mut pixels, assignment, &tile_pixels);
+    }
+    Ok(pixels)
+}
+
+/// Hands assignments to one worker until none are left.
+fn serve(
+    header: &Checkpoint,
+    assignments: &[Assignment],
+    progress: &Mutex<Progress>,
+    stream: TcpStream,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut r = BufReader::new(stream.try_clone()?);
+    let mut w = BufWriter::new(stream);
+
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC || Checkpoint::read_header(&mut r)? != *header {
+        write_u64(&mut w, REJECTED)?;
+        w.flush()?;
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "renders a different scene or configuration",
+        ));
+    }
+    // A worker that stalls gives its assignment back once `timeout` has passed.
+    r.get_ref().set_read_timeout(Some(timeout))?;
+
+    loop {
+        let next = {
+            let mut progress = progress.lock().unwrap();
+            match progress.pending.pop_front() {
+                Some(index) => Some(index),
+                None if progress.finished() => None,
+                None => {
+                    // Other workers may still fail and give their assignments back.
+                    drop(progress);
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            }
+        };
+        let Some(index) = next else {
+            write_u64(&mut w, DONE)?;
+            return w.flush();
+        };
+
+        let assignment = &assignments[index];
+        let result = (|| -> std::io::Result<Vec<FilmPixel>> {
+            write_u64(&mut w, ASSIGNMENT)?;
+            write_assignment(&mut w, assignment)?;
+            w.flush()?;
+
+            let deadline = Instant::now() + timeout;
+            read_tile_pixels(&mut r, assignment, header.aov_names.len(), Some(deadline))
+        })();
+
+        let mut progress = progress.lock().unwrap();
+        match result {
+            Ok(pixels) => progress.results[index] = Some(pixels),
+            Err(error) => {
+                progress.pending.push_front(index);
+                return Err(error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{film::Aov, renderer::tests::cornell_box_renderer};
+
+    use super::*;
+
+    #[test]
+    fn assignments_cover_every_sample_of_every_tile() {
+        let tiles: Vec<_> = (0..10)
+            .map(|x| Region {
+                x,
+                y: 0,
+                width: 1,
+                height: 1,
+            })
+            .collect();
+        let assignments = DistributedArgs {
+            tiles_per_assignment: 4,
+            samples_per_assignment: Some(3),
+            ..Default::default()
+        }
+        .assignments(&tiles, 7);
+
+        let mut covered = vec![0; 10];
+        for assignment in &assignments {
+            for tile in &assignment.tiles {
+                covered[tile.x] += assignment.samples.len();
+            }
+        }
+        assert_eq!(covered, vec![7; 10]);
+        assert_eq!(assignments.len(), 3 * 3);
+        assert_eq!(assignments[3].samples, 3..6);
+        assert_eq!(assignments[3].tiles, tiles[..4]);
+
+        let window = Region {
+            x: 0,
+            y: 0,
+            width: 10,
+            height: 1,
+        };
+        check_coverage(&window, 7, &assignments).unwrap();
+        assert!(check_coverage(&window, 7, &assignments[1..]).is_err());
+        let mut overlapping = assignments.clone();
+        overlapping.push(assignments[4].clone());
+        assert!(check_coverage(&window, 7, &overlapping).is_err());
+        assert!(check_coverage(&window, 8, &assignments).is_err());
+
+        let mut message = Vec::new();
+        write_assignment(&mut message, &assignments[5]).unwrap();
+        assert_eq!(
+            read_assignment(&mut &message[..], &window).unwrap(),
+            assignments[5]
+        );
+        let narrow = Region { width: 5, ..window };
+        assert!(read_assignment(&mut &message[..], &narrow).is_err());
+    }
+
+    #[test]
+    fn workers_render_the_same_image_as_one_process() {
+        let aovs = vec![Aov::ObjectId, Aov::SampleCount, Aov::Variance];
+        let renderer = cornell_box_renderer(8, 4).with_aovs(aovs.clone());
+        let header = Checkpoint {
+            fingerprint: 3,
+            width: renderer.width(),
+            height: renderer.height(),
+            aov_names: aovs.iter().map(|aov| aov.name().to_owned()).collect(),
+        };
+        let expected = renderer.render().unwrap();
+
+        for samples_per_assignment in [None, Some(3)] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let args = DistributedArgs {
+                tiles_per_assignment: 3,
+                samples_per_assignment,
+                ..Default::default()
+            };
+            let assignments = args.assignments(&renderer.tiles(), 4);
+
+            let pixels = std::thread::scope(|scope| {
+                for _ in 0..2 {
+                    scope.spawn(|| {
+                        let worker = cornell_box_renderer(8, 4).with_aovs(aovs.clone());
+                        work(&worker, &header, &address).unwrap();
+                    });
+                }
+                coordinate(
+                    &renderer,
+                    &header,
+                    &listener,
+                    &assignments,
+                    args.assignment_timeout(),
+                )
+                .unwrap()
+            });
+            let film = renderer.film(&pixels);
+
+            if samples_per_assignment.is_none() {
+                assert_eq!(film, expected);
+            } else {
+                // Adding up partial sums rounds differently from adding every sample.
+                for (pixel, expected) in film.beauty.pixels().iter().zip(expected.beauty.pixels()) {
+                    assert!((pixel - expected).norm() < 1e-9);
+                }
+                assert_eq!(film.aovs[..2], expected.aovs[..2]);
+            }
+        }
+    }
+
+    #[test]
+    fn assignments_of_stalled_workers_are_handed_out_again() {
+        let renderer = cornell_box_renderer(8, 4);
+        let header = Checkpoint {
+            fingerprint: 3,
+            width: renderer.width(),
+            height: renderer.height(),
+            aov_names: Vec::new(),
+        };
+        let expected = renderer.render().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let assignments = DistributedArgs {
+            tiles_per_assignment: 3,
+            ..Default::default()
+        }
+        .assignments(&renderer.tiles(), 4);
+
+        let pixels = std::thread::scope(|scope| {
+            let coordinator = scope.spawn(|| {
+                coordinate(
+                    &renderer,
+                    &header,
+                    &listener,
+                    &assignments,
+                    Duration::from_millis(500),
+                )
+                .unwrap()
+            });
+
+            // Takes the first assignment and never answers; the coordinator closes the
+            // connection once the timeout has passed.
+            let stream = TcpStream::connect(&address).unwrap();
+            let mut w = BufWriter::new(stream.try_clone().unwrap());
+            w.write_all(MAGIC).unwrap();
+            header.write_header(&mut w).unwrap();
+            w.flush().unwrap();
+            let mut r = BufReader::new(stream);
+            assert_eq!(read_u64(&mut r).unwrap(), ASSIGNMENT);
+            read_assignment(&mut r, &renderer.window()).unwrap();
+
+            work(&renderer, &header, &address).unwrap();
+            assert!(read_u64(&mut r).is_err());
+            coordinator.join().unwrap()
+        });
+
+        assert_eq!(renderer.film(&pixels), expected);
+    }
+}
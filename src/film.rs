@@ -0,0 +1,371 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    checkpoint::{read_f64, read_u64, write_f64, write_u64},
+    helpers::{gray_scale, Color},
+    image::{
+        denoise::{DenoiserArgs, Features},
+        Image, OutputArgs,
+    },
+    object::intersection::{Intersection, MaterialInformation},
+    shader::Lighting,
+};
+
+/// Auxiliary pass recorded next to the beauty image, from the first hit of every camera
+/// sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Aov {
+    /// Diffuse plus specular reflectance, after texturing.
+    Albedo,
+    /// World space shading normal.
+    Normal,
+    /// Distance from the camera along the ray.
+    Depth,
+    /// World space position.
+    Position,
+    MaterialId,
+    ObjectId,
+    /// Light emitted at the first hit or reflected there straight from a light.
+    Direct,
+    /// Light that bounced more than once before reaching the camera.
+    Indirect,
+    SampleCount,
+    /// Estimated variance of the luminance of the pixel, that is of the mean of its samples.
+    Variance,
+}
+
+/// How the values of the samples of one pixel are combined.
+enum Reduction {
+    Average,
+    /// Identifiers are not blended, so the first sample decides.
+    First,
+    Sum,
+    /// Computed from the beauty samples rather than from per-sample values.
+    Variance,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 10] = [
+        Self::Albedo,
+        Self::Normal,
+        Self::Depth,
+        Self::Position,
+        Self::MaterialId,
+        Self::ObjectId,
+        Self::Direct,
+        Self::Indirect,
+        Self::SampleCount,
+        Self::Variance,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Albedo => "albedo",
+            Self::Normal => "normal",
+            Self::Depth => "depth",
+            Self::Position => "position",
+            Self::MaterialId => "material_id",
+            Self::ObjectId => "object_id",
+            Self::Direct => "direct",
+            Self::Indirect => "indirect",
+            Self::SampleCount => "sample_count",
+            Self::Variance => "variance",
+        }
+    }
+
+    fn reduction(&self) -> Reduction {
+        match self {
+            Self::MaterialId | Self::ObjectId => Reduction::First,
+            Self::SampleCount => Reduction::Sum,
+            Self::Variance => Reduction::Variance,
+            _ => Reduction::Average,
+        }
+    }
+
+    /// Value of one sample. Misses are zero, except for identifiers, which are -1.
+    fn value(&self, intersection: &Option<Intersection>, lighting: &Lighting) -> Color {
+        let id = |id: Option<usize>| Color::repeat(id.map_or(-1.0, |id| id as f64));
+        let hit =
+            |value: fn(&Intersection) -> Color| intersection.as_ref().map_or(Color::zeros(), value);
+
+        match self {
+            Self::Albedo => hit(albedo),
+            Self::Normal => hit(|intersection| *intersection.shading_normal()),
+            Self::Depth => hit(|intersection| Color::repeat(intersection.depth())),
+            Self::Position => hit(|intersection| *intersection.point()),
+            Self::MaterialId => id(intersection
+                .as_ref()
+                .and_then(|intersection| intersection.brdf.as_ref())
+                .map(|&MaterialInformation { material_id, .. }| material_id)),
+            Self::ObjectId => id(intersection
+                .as_ref()
+                .and_then(|intersection| intersection.object_id)),
+            Self::Direct => lighting.direct,
+            Self::Indirect => lighting.indirect,
+            Self::SampleCount => Color::repeat(1.0),
+            Self::Variance => Color::zeros(),
+        }
+    }
+}
+
+fn albedo(intersection: &Intersection) -> Color {
+    let Some(material) = intersection.brdf() else {
+        return Color::zeros();
+    };
+    let [diffuse, specular] = [material.diffuse, material.specular].map(|color| {
+        color.map_or(Color::zeros(), |c| {
+            Color::new(c[0].into(), c[1].into(), c[2].into())
+        })
+    });
+
+    (diffuse + specular).map(|value| value.min(1.0))
+}
+
+/// AOVs that guide [`Film::denoise`].
+pub const DENOISER_FEATURES: [Aov; 4] = [Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Variance];
+
+/// Accumulated samples of one pixel: the beauty radiance and every requested AOV.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilmPixel {
+    radiance: Color,
+    luminance_squares: f64,
+    aovs: Vec<Color>,
+    samples: usize,
+}
+
+impl FilmPixel {
+    pub fn new(aovs: &[Aov]) -> Self {
+        Self {
+            radiance: Color::zeros(),
+            luminance_squares: 0.0,
+            aovs: vec![Color::zeros(); aovs.len()],
+            samples: 0,
+        }
+    }
+
+    pub fn add_sample(
+        &mut self,
+        aovs: &[Aov],
+        intersection: &Option<Intersection>,
+        lighting: &Lighting,
+    ) {
+        let radiance = lighting.total();
+        self.radiance += radiance;
+        self.luminance_squares += gray_scale(&radiance).powi(2);
+        for (aov, value) in aovs.iter().zip(&mut self.aovs) {
+            match aov.reduction() {
+                Reduction::First if self.samples > 0 => {}
+                Reduction::First => *value = aov.value(intersection, lighting),
+                Reduction::Average | Reduction::Sum => *value += aov.value(intersection, lighting),
+                Reduction::Variance => {}
+            }
+        }
+        self.samples += 1;
+    }
+
+    /// Adds the samples of `other`, taken with different sample indices or none at all,
+    /// as if they had been added to this pixel.
+    pub fn merge(&mut self, aovs: &[Aov], other: &FilmPixel) {
+        if other.samples == 0 {
+            return;
+        }
+        for ((aov, value), other_value) in aovs.iter().zip(&mut self.aovs).zip(&other.aovs) {
+            match aov.reduction() {
+                Reduction::First if self.samples > 0 => {}
+                Reduction::First => *value = *other_value,
+                Reduction::Average | Reduction::Sum => *value += other_value,
+                Reduction::Variance => {}
+            }
+        }
+        self.radiance += other.radiance;
+        self.luminance_squares += other.luminance_squares;
+        self.samples += other.samples;
+    }
+
+    fn resolve(&self, aovs: &[Aov], index: Option<usize>) -> Color {
+        let samples = self.samples.max(1) as f64;
+        let Some(index) = index else {
+            return self.radiance / samples;
+        };
+
+        match aovs[index].reduction() {
+            Reduction::Average => self.aovs[index] / samples,
+            Reduction::First | Reduction::Sum => self.aovs[index],
+            Reduction::Variance => Color::repeat(self.variance()),
+        }
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> std::io::Result<()> {
+        write_u64(w, self.samples as u64)?;
+        write_f64(w, self.luminance_squares)?;
+        for color in std::iter::once(&self.radiance).chain(&self.aovs) {
+            for channel in color.iter() {
+                write_f64(w, *channel)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read, aov_count: usize) -> std::io::Result<Self> {
+        let samples = read_u64(r)? as usize;
+        let luminance_squares = read_f64(r)?;
+        let mut read_color = || -> std::io::Result<Color> {
+            Ok(Color::new(read_f64(r)?, read_f64(r)?, read_f64(r)?))
+        };
+        let radiance = read_color()?;
+        let aovs = (0..aov_count)
+            .map(|_| read_color())
+            .collect::<std::io::Result<_>>()?;
+
+        Ok(Self {
+            radiance,
+            luminance_squares,
+            aovs,
+            samples,
+        })
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn mean_luminance(&self) -> f64 {
+        gray_scale(&self.radiance) / self.samples.max(1) as f64
+    }
+
+    /// Sample variance of the luminance divided by the sample count, which estimates the
+    /// squared error of the pixel.
+    pub fn variance(&self) -> f64 {
+        if self.samples < 2 {
+            return 0.0;
+        }
+        let samples = self.samples as f64;
+        let mean = gray_scale(&self.radiance) / samples;
+        let sample_variance = (self.luminance_squares - samples * mean * mean) / (samples - 1.0);
+        sample_variance.max(0.0) / samples
+    }
+}
+
+/// The beauty image and the AOVs of a finished render.
+#[derive(Debug, PartialEq)]
+pub struct Film {
+    pub beauty: Image,
+    pub aovs: Vec<(Aov, Image)>,
+    /// The beauty image before denoising, when it is kept.
+    pub noisy: Option<Image>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, aovs: &[Aov], pixels: &[FilmPixel]) -> Self {
+        let image = |index: Option<usize>| {
+            Image::new(
+                width,
+                height,
+                pixels
+                    .iter()
+                    .map(|pixel| pixel.resolve(aovs, index))
+                    .collect(),
+            )
+        };
+
+        Self {
+            beauty: image(None),
+            aovs: aovs
+                .iter()
+                .enumerate()
+                .map(|(index, aov)| (*aov, image(Some(index))))
+                .collect(),
+            noisy: None,
+        }
+    }
+
+    pub fn aov(&self, aov: Aov) -> Option<&Image> {
+        self.aovs
+            .iter()
+            .find(|(recorded, _)| *recorded == aov)
+            .map(|(_, image)| image)
+    }
+
+    /// Multiplies the beauty image and the light passes by the exposure `gains` of every
+    /// pixel, and the variance by their squares, so that the denoiser compares the noise
+    /// with the exposed image.
+    pub fn expose(&mut self, gains: &[f64]) {
+        self.beauty.scale(gains);
+        let squares: Vec<f64> = gains.iter().map(|gain| gain * gain).collect();
+        for (aov, image) in &mut self.aovs {
+            match aov {
+                Aov::Direct | Aov::Indirect => image.scale(gains),
+                Aov::Variance => image.scale(&squares),
+                _ => {}
+            }
+        }
+    }
+
+    /// Replaces the beauty image with its denoised version. The film must hold the
+    /// [`DENOISER_FEATURES`] AOVs.
+    pub fn denoise(&mut self, denoiser: &DenoiserArgs) {
+        let feature = |aov: Aov| {
+            self.aov(aov)
+                .unwrap_or_else(|| panic!("{} AOV required by the denoiser", aov.name()))
+        };
+        let denoised = denoiser.denoise(
+            &self.beauty,
+            &Features {
+                albedo: feature(Aov::Albedo),
+                normal: feature(Aov::Normal),
+                depth: feature(Aov::Depth),
+                variance: feature(Aov::Variance),
+            },
+        );
+
+        let noisy = std::mem::replace(&mut self.beauty, denoised);
+        if denoiser.keep_noisy {
+            self.noisy = Some(noisy);
+        }
+    }
+
+    /// Saves the beauty image to `path` and every AOV listed in `output` as float OpenEXR
+    /// next to it, named after the pass: `render.png` comes with `render_albedo.exr`. AOVs
+    /// keep their values, without exposure compensation.
+    pub fn save(&self, path: &str, output: &OutputArgs) -> std::io::Result<()> {
+        self.beauty.save(path, output)?;
+        self.save_passes(path, output)
+    }
+
+    /// Saves like [`Self::save`], but with the beauty image pasted at `x`, `y` into the
+    /// image at `base`. The other passes only hold the pasted window.
+    pub fn save_pasted(
+        &self,
+        path: &str,
+        output: &OutputArgs,
+        base: &str,
+        x: usize,
+        y: usize,
+    ) -> std::io::Result<()> {
+        self.beauty.save_pasted(path, output, base, x, y)?;
+        self.save_passes(path, output)
+    }
+
+    fn save_passes(&self, path: &str, output: &OutputArgs) -> std::io::Result<()> {
+        let path = Path::new(path);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        if let Some(noisy) = &self.noisy {
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
+            let noisy_path = path.with_file_name(format!("{stem}_noisy.{extension}"));
+            noisy.save(&noisy_path.to_string_lossy(), output)?;
+        }
+        for (aov, image) in &self.aovs {
+            if !output.aovs().contains(aov) {
+                continue;
+            }
+            let aov_path = path.with_file_name(format!("{stem}_{}.exr", aov.name()));
+            image.save(&aov_path.to_string_lossy(), &output.unexposed())?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,4 @@
 use nalgebra::Matrix3;
-use tobj::Material;
 
 pub type Vec3 = nalgebra::Vector3<f64>;
 pub type Vec2 = nalgebra::Vector2<f64>;
@@ -22,7 +21,7 @@ impl Rotateable for Vec3 {
     }
 
     fn face_forward(&self, wo: &Vec3) -> Self {
-        if self.dot(&wo) < 0.0 {
+        if self.dot(wo) < 0.0 {
             -1. * self
         } else {
             *self
@@ -42,16 +41,6 @@ impl CoordinateSystemProvider for Vec3 {
     }
 }
 
-pub fn has_component<F, Component>(material: &Material, component_getter: F) -> bool
-where
-    Component: Zeroable,
-    F: FnOnce(&Material) -> Option<Component>,
-{
-    let component = component_getter(material);
-
-    component.is_some_and(|value| !value.is_zero())
-}
-
 pub trait Zeroable {
     fn is_zero(&self) -> bool;
 }
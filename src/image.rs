@@ -1,21 +1,92 @@
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
 use std::{fs::File, path::Path};
 
-use image::{ImageBuffer, ImageFormat, RgbImage};
+use image::{
+    codecs::hdr::HdrEncoder, DynamicImage, ImageBuffer, ImageFormat, Rgb, Rgb32FImage, RgbImage,
+};
+use serde::Deserialize;
 
-use crate::helpers::Color;
+use crate::{film::Aov, helpers::Color};
 
-#[derive(Debug)]
+use self::tone_mapping::ToneMapping;
+
+pub mod denoise;
+pub mod tone_mapping;
+
+/// How radiance becomes 8 bit pixel values. Float formats store the exposed radiance,
+/// without tone mapping or encoding.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutputArgs {
+    #[serde(default)]
+    tone_mapping: ToneMapping,
+    /// Exposure compensation in stops, applied to every format, before tone mapping.
+    #[serde(default)]
+    exposure: f64,
+    /// Encode with the sRGB transfer function rather than writing linear values.
+    #[serde(default = "default_srgb")]
+    srgb: bool,
+    /// Auxiliary passes saved next to the image.
+    #[serde(default)]
+    aovs: Vec<Aov>,
+    /// Also save the render statistics next to the image, as `{stem}_statistics.json`.
+    #[serde(default)]
+    pub statistics: bool,
+}
+
+fn default_srgb() -> bool {
+    true
+}
+
+impl Default for OutputArgs {
+    fn default() -> Self {
+        Self {
+            tone_mapping: ToneMapping::default(),
+            exposure: 0.0,
+            srgb: default_srgb(),
+            aovs: Vec::new(),
+            statistics: false,
+        }
+    }
+}
+
+impl OutputArgs {
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    /// The same settings without exposure compensation, for images that must keep their
+    /// values, like auxiliary passes.
+    pub fn unexposed(&self) -> Self {
+        Self {
+            exposure: 0.0,
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    /// 8 bit formats, clamped to `[0, 1]` before quantization.
+    LowDynamicRange(ImageFormat),
+    /// 32 bit float formats written through the `image` crate.
+    HighDynamicRange(ImageFormat),
+    /// Radiance RGBE, which `write_to` does not support; encoded with `HdrEncoder`.
+    Radiance,
+    /// Portable float map, which the `image` crate cannot encode.
+    Pfm,
+}
+
+/// Linear radiance of every pixel, quantized only when saved to an 8 bit format.
+#[derive(Debug, PartialEq)]
 pub struct Image {
     width: usize,
     height: usize,
-    image_data: Vec<u8>,
+    image_data: Vec<Color>,
 }
 
 impl Image {
     pub fn new(width: usize, height: usize, image_data: Vec<Color>) -> Self {
         assert!(width * height == image_data.len());
-        let image_data = Self::tone_map(width, height, image_data);
         Self {
             width,
             height,
@@ -23,59 +94,278 @@ impl Image {
         }
     }
 
-    pub fn save(self, path: &str) -> std::io::Result<()> {
-        let image: RgbImage =
-            ImageBuffer::from_raw(self.width as u32, self.height as u32, self.image_data)
-                .expect("Error creating the image buffer");
+    pub fn pixels(&self) -> &[Color] {
+        &self.image_data
+    }
 
+    /// Multiplies every pixel by its factor in `gains`.
+    pub fn scale(&mut self, gains: &[f64]) {
+        for (pixel, gain) in self.image_data.iter_mut().zip(gains) {
+            *pixel *= *gain;
+        }
+    }
+
+    pub fn save(&self, path: &str, output: &OutputArgs) -> std::io::Result<()> {
+        let format = Self::format(path).ok_or_else(unsupported_format)?;
         let file = File::create(path)?;
-        let ref mut w = BufWriter::new(file);
-        let image_format = Self::format(path).expect("Invalid image format");
+        let mut w = BufWriter::new(file);
 
-        image.write_to(w, image_format).expect("Error saving image");
-        Ok(())
+        match format {
+            OutputFormat::LowDynamicRange(image_format) => {
+                let image: RgbImage = ImageBuffer::from_raw(
+                    self.width as u32,
+                    self.height as u32,
+                    self.tone_map(output),
+                )
+                .expect("Error creating the image buffer");
+
+                image
+                    .write_to(&mut w, image_format)
+                    .map_err(std::io::Error::other)?;
+            }
+            OutputFormat::HighDynamicRange(image_format) => {
+                let image: Rgb32FImage = ImageBuffer::from_raw(
+                    self.width as u32,
+                    self.height as u32,
+                    self.to_f32(output),
+                )
+                .expect("Error creating the image buffer");
+
+                image
+                    .write_to(&mut w, image_format)
+                    .map_err(std::io::Error::other)?;
+            }
+            OutputFormat::Radiance => {
+                let pixels: Vec<Rgb<f32>> = self
+                    .to_f32(output)
+                    .chunks_exact(3)
+                    .map(|pixel| Rgb([pixel[0], pixel[1], pixel[2]]))
+                    .collect();
+
+                HdrEncoder::new(&mut w)
+                    .encode(&pixels, self.width, self.height)
+                    .map_err(std::io::Error::other)?;
+            }
+            OutputFormat::Pfm => self.write_pfm(&mut w, output)?,
+        }
+
+        w.flush()
+    }
+
+    /// Saves the image at `path` after pasting it at `x`, `y` into a copy of the image at
+    /// `base`. The image is exposed and, for 8 bit formats, tone mapped before it is
+    /// pasted, so that the pixels of `base` are kept as they are.
+    pub fn save_pasted(
+        &self,
+        path: &str,
+        output: &OutputArgs,
+        base: &str,
+        x: usize,
+        y: usize,
+    ) -> std::io::Result<()> {
+        let base = open(base)?;
+        let (x, y) = (x as i64, y as i64);
+
+        match Self::format(path).ok_or_else(unsupported_format)? {
+            OutputFormat::LowDynamicRange(image_format) => {
+                let mut base = base.into_rgb8();
+                let window: RgbImage = ImageBuffer::from_raw(
+                    self.width as u32,
+                    self.height as u32,
+                    self.tone_map(output),
+                )
+                .expect("Error creating the image buffer");
+                image::imageops::replace(&mut base, &window, x, y);
+
+                let mut w = BufWriter::new(File::create(path)?);
+                base.write_to(&mut w, image_format)
+                    .map_err(std::io::Error::other)?;
+                w.flush()
+            }
+            _ => {
+                let mut base = base.into_rgb32f();
+                let window: Rgb32FImage = ImageBuffer::from_raw(
+                    self.width as u32,
+                    self.height as u32,
+                    self.to_f32(output),
+                )
+                .expect("Error creating the image buffer");
+                image::imageops::replace(&mut base, &window, x, y);
+
+                let pasted = Image::new(
+                    base.width() as usize,
+                    base.height() as usize,
+                    base.pixels()
+                        .map(|Rgb([r, g, b])| Color::new(*r as f64, *g as f64, *b as f64))
+                        .collect(),
+                );
+                // The window is exposed already and `base` is kept as it is.
+                pasted.save(path, &output.unexposed())
+            }
+        }
+    }
+
+    /// Size of the image at `path`.
+    pub fn dimensions(path: &str) -> std::io::Result<(usize, usize)> {
+        let image = open(path)?;
+        Ok((image.width() as usize, image.height() as usize))
     }
 
     pub fn valid_format(path: &str) -> bool {
         Self::format(path).is_some()
     }
 
-    fn format(path: &str) -> Option<ImageFormat> {
+    fn format(path: &str) -> Option<OutputFormat> {
         let path = Path::new(path);
-        match path.extension()?.to_str()? {
-            "png" => Some(ImageFormat::Png),
-            "jpeg" => Some(ImageFormat::Jpeg),
-            "jpg" => Some(ImageFormat::Jpeg),
-            "gif" => Some(ImageFormat::Gif),
-            "webp" => Some(ImageFormat::WebP),
-            "tiff" => Some(ImageFormat::Tiff),
-            "tga" => Some(ImageFormat::Tga),
-            "bmp" => Some(ImageFormat::Bmp),
-            "ico" => Some(ImageFormat::Ico),
-            "hdr" => Some(ImageFormat::Hdr),
-            "openexr" => Some(ImageFormat::OpenExr),
-            "pnm" => Some(ImageFormat::Pnm),
-            "farbfeld" => Some(ImageFormat::Farbfeld),
-            "avif" => Some(ImageFormat::Avif),
-            _ => None,
+        let image_format = match path.extension()?.to_str()? {
+            "png" => ImageFormat::Png,
+            "jpeg" => ImageFormat::Jpeg,
+            "jpg" => ImageFormat::Jpeg,
+            "gif" => ImageFormat::Gif,
+            "webp" => ImageFormat::WebP,
+            "tiff" => ImageFormat::Tiff,
+            "tga" => ImageFormat::Tga,
+            "bmp" => ImageFormat::Bmp,
+            "ico" => ImageFormat::Ico,
+            "hdr" => return Some(OutputFormat::Radiance),
+            "exr" | "openexr" => return Some(OutputFormat::HighDynamicRange(ImageFormat::OpenExr)),
+            "pfm" => return Some(OutputFormat::Pfm),
+            "pnm" => ImageFormat::Pnm,
+            "farbfeld" => ImageFormat::Farbfeld,
+            "avif" => ImageFormat::Avif,
+            _ => return None,
+        };
+
+        Some(OutputFormat::LowDynamicRange(image_format))
+    }
+
+    /// Exposed radiance of every pixel, channel by channel.
+    fn to_f32(&self, output: &OutputArgs) -> Vec<f32> {
+        let exposure = output.exposure.exp2();
+        self.image_data
+            .iter()
+            .map(|pixel| pixel * exposure)
+            .flat_map(|pixel| [pixel.x as f32, pixel.y as f32, pixel.z as f32])
+            .collect()
+    }
+
+    /// Writes a color PFM: a text header followed by little endian floats, bottom row
+    /// first.
+    fn write_pfm(&self, w: &mut impl Write, output: &OutputArgs) -> std::io::Result<()> {
+        write!(w, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+
+        for row in self.to_f32(output).chunks_exact(3 * self.width).rev() {
+            for channel in row {
+                w.write_all(&channel.to_le_bytes())?;
+            }
         }
+        Ok(())
     }
 
-    fn tone_map(width: usize, height: usize, image_data: Vec<Color>) -> Vec<u8> {
-        let mut pixel_data = Vec::with_capacity(width * height);
+    fn tone_map(&self, output: &OutputArgs) -> Vec<u8> {
+        let exposure = output.exposure.exp2();
 
-        for j in 0..height {
-            for i in 0..width {
-                let index = (j * width) + i;
-                let pixel = image_data[index];
-                let pixel_color = [
-                    (pixel.x.min(1.0) * 255.0) as u8,
-                    (pixel.y.min(1.0) * 255.0) as u8,
-                    (pixel.z.min(1.0) * 255.0) as u8,
-                ];
-                pixel_data.extend_from_slice(&pixel_color);
+        self.image_data
+            .iter()
+            .flat_map(|pixel| {
+                let mapped = output.tone_mapping.apply(pixel * exposure);
+                [mapped.x, mapped.y, mapped.z].map(|value| {
+                    let value = value.clamp(0.0, 1.0);
+                    let encoded = if output.srgb {
+                        linear_to_srgb(value)
+                    } else {
+                        value
+                    };
+                    (encoded * 255.0).round() as u8
+                })
+            })
+            .collect()
+    }
+}
+
+fn unsupported_format() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, "unsupported image format")
+}
+
+/// Decodes the image at `path`, recognizing its format from the content, since the
+/// `image` crate does not know every extension that [`Image::save`] accepts.
+fn open(path: &str) -> std::io::Result<DynamicImage> {
+    image::io::Reader::open(path)?
+        .with_guessed_format()?
+        .decode()
+        .map_err(std::io::Error::other)
+}
+
+fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> Image {
+        let pixels = (0..6)
+            .map(|index| Color::new(index as f64 * 2.5, 0.25, 1.0 / (index + 1) as f64))
+            .collect();
+        Image::new(3, 2, pixels)
+    }
+
+    #[test]
+    fn float_formats_keep_values_above_one() {
+        let directory = std::env::temp_dir().join(format!("raytracer-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        for extension in ["exr", "hdr"] {
+            let path = directory.join(format!("gradient.{extension}"));
+            gradient()
+                .save(path.to_str().unwrap(), &OutputArgs::default())
+                .unwrap();
+
+            let read = image::open(&path).unwrap().into_rgb32f();
+            let expected = gradient().to_f32(&OutputArgs::default());
+            for (read, expected) in read.as_raw().chunks(3).zip(expected.chunks(3)) {
+                // Radiance HDR stores an 8 bit mantissa per channel with a shared exponent.
+                let tolerance = expected.iter().copied().fold(0.0, f32::max) / 128.0;
+                for (read, expected) in read.iter().zip(expected) {
+                    assert!((read - expected).abs() <= tolerance, "{extension}");
+                }
             }
         }
-        pixel_data
+
+        let path = directory.join("gradient.pfm");
+        let first_value = |output: &OutputArgs| {
+            gradient().save(path.to_str().unwrap(), output).unwrap();
+            let pfm = std::fs::read(&path).unwrap();
+            let header = b"PF\n3 2\n-1.0\n";
+            assert_eq!(&pfm[..header.len()], header);
+            f32::from_le_bytes(pfm[header.len()..header.len() + 4].try_into().unwrap())
+        };
+        // The bottom row comes first, so the first pixel written is (0, 1).
+        assert_eq!(first_value(&OutputArgs::default()), 7.5);
+        // Float formats are exposed like 8 bit ones.
+        let brighter = OutputArgs {
+            exposure: 1.0,
+            ..Default::default()
+        };
+        assert_eq!(first_value(&brighter), 15.0);
+
+        // Unsupported formats are errors, for pasted windows too.
+        let unsupported = directory.join("gradient.txt");
+        let unsupported = unsupported.to_str().unwrap();
+        let output = OutputArgs::default();
+        assert!(gradient().save(unsupported, &output).is_err());
+        let base = directory.join("gradient.exr");
+        let base = base.to_str().unwrap();
+        assert!(gradient()
+            .save_pasted(unsupported, &output, base, 0, 0)
+            .is_err());
+        assert!(!Path::new(unsupported).exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
     }
 }
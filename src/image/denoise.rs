@@ -0,0 +1,235 @@
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Deserialize;
+
+use crate::helpers::{gray_scale, Color};
+
+use super::Image;
+
+/// Taps of the B3 spline, the smoothing kernel of every à-trous level.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010). Every level blurs with a
+/// 5x5 kernel whose taps are twice as far apart as in the previous level, weighting each
+/// tap by how similar its albedo, normal and depth are to the center pixel, and by how
+/// much its luminance differs relative to the estimated noise, as in SVGF.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DenoiserArgs {
+    #[serde(default = "default_iterations")]
+    iterations: u32,
+    /// Luminance tolerance in standard deviations of the pixel's noise.
+    #[serde(default = "default_sigma_luminance")]
+    sigma_luminance: f64,
+    #[serde(default = "default_sigma_albedo")]
+    sigma_albedo: f64,
+    #[serde(default = "default_sigma_normal")]
+    sigma_normal: f64,
+    /// Tolerance of depth differences relative to the depth of the center pixel.
+    #[serde(default = "default_sigma_depth")]
+    sigma_depth: f64,
+    /// Also save the unfiltered image, with `_noisy` appended to the output file name.
+    #[serde(default)]
+    pub keep_noisy: bool,
+}
+
+fn default_iterations() -> u32 {
+    5
+}
+
+fn default_sigma_luminance() -> f64 {
+    4.0
+}
+
+fn default_sigma_albedo() -> f64 {
+    0.1
+}
+
+fn default_sigma_normal() -> f64 {
+    0.3
+}
+
+fn default_sigma_depth() -> f64 {
+    0.1
+}
+
+impl Default for DenoiserArgs {
+    fn default() -> Self {
+        Self {
+            iterations: default_iterations(),
+            sigma_luminance: default_sigma_luminance(),
+            sigma_albedo: default_sigma_albedo(),
+            sigma_normal: default_sigma_normal(),
+            sigma_depth: default_sigma_depth(),
+            keep_noisy: false,
+        }
+    }
+}
+
+/// Feature buffers that guide the filter, from the first hit of every pixel.
+pub struct Features<'a> {
+    pub albedo: &'a Image,
+    pub normal: &'a Image,
+    pub depth: &'a Image,
+    /// Variance of the luminance of every pixel.
+    pub variance: &'a Image,
+}
+
+impl DenoiserArgs {
+    /// Filters the illumination, that is `color` divided by the albedo, so that texture
+    /// detail is not blurred, and multiplies the albedo back in.
+    pub fn denoise(&self, color: &Image, features: &Features) -> Image {
+        let demodulate =
+            |albedo: &Color| albedo.map(|value| if value > 1e-3 { value } else { 1.0 });
+
+        let mut illumination: Vec<(Color, f64)> = color
+            .image_data
+            .iter()
+            .zip(&features.albedo.image_data)
+            .zip(&features.variance.image_data)
+            .map(|((color, albedo), variance)| {
+                let albedo = demodulate(albedo);
+                (
+                    color.component_div(&albedo),
+                    variance.x / gray_scale(&albedo).powi(2),
+                )
+            })
+            .collect();
+
+        for level in 0..self.iterations {
+            illumination =
+                self.filter_level(color.width, color.height, &illumination, features, level);
+        }
+
+        Image::new(
+            color.width,
+            color.height,
+            illumination
+                .iter()
+                .zip(&features.albedo.image_data)
+                .map(|((illumination, _), albedo)| illumination.component_mul(&demodulate(albedo)))
+                .collect(),
+        )
+    }
+
+    /// One level of the filter over illumination and variance pairs. The variance is
+    /// filtered with the squared weights, so it keeps tracking the remaining noise.
+    fn filter_level(
+        &self,
+        width: usize,
+        height: usize,
+        illumination: &[(Color, f64)],
+        features: &Features,
+        level: u32,
+    ) -> Vec<(Color, f64)> {
+        let step = 1i64 << level;
+        let weight =
+            |distance_squared: f64, sigma: f64| (-distance_squared / (sigma * sigma)).exp();
+
+        (0..width * height)
+            .into_par_iter()
+            .map(|index| {
+                let (x, y) = ((index % width) as i64, (index / width) as i64);
+                let (color, variance) = illumination[index];
+                let luminance = gray_scale(&color);
+                let luminance_tolerance = self.sigma_luminance * variance.sqrt() + 1e-6;
+                let albedo = features.albedo.image_data[index];
+                let normal = features.normal.image_data[index];
+                let depth = features.depth.image_data[index].x;
+
+                let mut sum = Color::zeros();
+                let mut variance_sum = 0.0;
+                let mut total_weight = 0.0;
+                for (j, kernel_y) in KERNEL.iter().enumerate() {
+                    for (i, kernel_x) in KERNEL.iter().enumerate() {
+                        let qx = x + (i as i64 - 2) * step;
+                        let qy = y + (j as i64 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= width as i64 || qy >= height as i64 {
+                            continue;
+                        }
+                        let q = qy as usize * width + qx as usize;
+                        let (tap_color, tap_variance) = illumination[q];
+
+                        let depth_difference = (features.depth.image_data[q].x - depth)
+                            / depth.abs().max(f64::EPSILON);
+                        let tap_weight = kernel_x
+                            * kernel_y
+                            * (-(gray_scale(&tap_color) - luminance).abs() / luminance_tolerance)
+                                .exp()
+                            * weight(
+                                (features.albedo.image_data[q] - albedo).norm_squared(),
+                                self.sigma_albedo,
+                            )
+                            * weight(
+                                (features.normal.image_data[q] - normal).norm_squared(),
+                                self.sigma_normal,
+                            )
+                            * weight(depth_difference * depth_difference, self.sigma_depth);
+
+                        sum += tap_color * tap_weight;
+                        variance_sum += tap_variance * tap_weight * tap_weight;
+                        total_weight += tap_weight;
+                    }
+                }
+
+                // The center tap always has a positive weight.
+                (
+                    sum / total_weight,
+                    variance_sum / (total_weight * total_weight),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooths_noise_but_keeps_geometric_edges() {
+        const SIZE: usize = 32;
+
+        // Two walls meeting at a vertical edge, a dark one on the left and a bright one on
+        // the right, both with uniform noise.
+        let left = |index: usize| index % SIZE < SIZE / 2;
+        let mut rng = fastrand::Rng::with_seed(5);
+        let color = Image::new(
+            SIZE,
+            SIZE,
+            (0..SIZE * SIZE)
+                .map(|index| {
+                    let noise = rng.f64() * 0.2 - 0.1;
+                    Color::repeat(if left(index) { 0.2 } else { 0.8 } + noise)
+                })
+                .collect(),
+        );
+        let albedo = Image::new(SIZE, SIZE, vec![Color::repeat(0.5); SIZE * SIZE]);
+        let normal = Image::new(
+            SIZE,
+            SIZE,
+            (0..SIZE * SIZE)
+                .map(|index| if left(index) { Color::x() } else { Color::z() })
+                .collect(),
+        );
+        let depth = Image::new(SIZE, SIZE, vec![Color::repeat(10.0); SIZE * SIZE]);
+        // Uniform noise of width 0.2 has a variance of 0.2² / 12.
+        let variance = Image::new(SIZE, SIZE, vec![Color::repeat(0.04 / 12.0); SIZE * SIZE]);
+
+        let denoised = DenoiserArgs::default().denoise(
+            &color,
+            &Features {
+                albedo: &albedo,
+                normal: &normal,
+                depth: &depth,
+                variance: &variance,
+            },
+        );
+
+        for (index, value) in denoised.image_data.iter().enumerate() {
+            let expected = if left(index) { 0.2 } else { 0.8 };
+            assert!(
+                (value.x - expected).abs() < 0.03,
+                "{value} at {index}, expected {expected}"
+            );
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use serde::Deserialize;
+
+use crate::helpers::Color;
+
+/// Curve mapping linear radiance to display values; anything left above one clips when
+/// quantized.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(tag = "type")]
+pub enum ToneMapping {
+    /// No curve; values above one clip.
+    #[default]
+    Clamp,
+    /// `L / (1 + L)` on luminance, which keeps the hue of bright pixels.
+    Reinhard,
+    /// Reinhard with a white point: luminance `white_point` and above maps to one.
+    ExtendedReinhard {
+        #[serde(default = "default_white_point")]
+        white_point: f64,
+    },
+    /// Narkowicz's fit of the ACES filmic curve, applied per channel.
+    Aces,
+    /// Uchimura's Gran Turismo curve: a toe, a linear section and an exponential
+    /// shoulder, applied per channel.
+    Uchimura(UchimuraCurve),
+}
+
+fn default_white_point() -> f64 {
+    4.0
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct UchimuraCurve {
+    #[serde(default = "default_max_brightness")]
+    max_brightness: f64,
+    #[serde(default = "default_contrast")]
+    contrast: f64,
+    #[serde(default = "default_linear_start")]
+    linear_start: f64,
+    #[serde(default = "default_linear_length")]
+    linear_length: f64,
+    #[serde(default = "default_black_tightness")]
+    black_tightness: f64,
+    #[serde(default)]
+    pedestal: f64,
+}
+
+fn default_max_brightness() -> f64 {
+    1.0
+}
+
+fn default_contrast() -> f64 {
+    1.0
+}
+
+fn default_linear_start() -> f64 {
+    0.22
+}
+
+fn default_linear_length() -> f64 {
+    0.4
+}
+
+fn default_black_tightness() -> f64 {
+    1.33
+}
+
+impl Default for UchimuraCurve {
+    fn default() -> Self {
+        Self {
+            max_brightness: default_max_brightness(),
+            contrast: default_contrast(),
+            linear_start: default_linear_start(),
+            linear_length: default_linear_length(),
+            black_tightness: default_black_tightness(),
+            pedestal: 0.0,
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn apply(&self, color: Color) -> Color {
+        match self {
+            Self::Clamp => color,
+            Self::Reinhard => scale_luminance(color, |luminance| luminance / (1.0 + luminance)),
+            Self::ExtendedReinhard { white_point } => scale_luminance(color, |luminance| {
+                luminance * (1.0 + luminance / (white_point * white_point)) / (1.0 + luminance)
+            }),
+            Self::Aces => color.map(aces),
+            Self::Uchimura(curve) => color.map(|value| curve.apply(value)),
+        }
+    }
+}
+
+fn luminance(color: &Color) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+fn scale_luminance(color: Color, curve: impl Fn(f64) -> f64) -> Color {
+    let luminance = luminance(&color);
+    if luminance <= 0.0 {
+        return Color::zeros();
+    }
+    color * (curve(luminance) / luminance)
+}
+
+/// The fit expects the input scaled by 0.6 to match the exposure of the reference curve.
+fn aces(value: f64) -> f64 {
+    let x = value.max(0.0) * 0.6;
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl UchimuraCurve {
+    fn apply(&self, x: f64) -> f64 {
+        let Self {
+            max_brightness: p,
+            contrast: a,
+            linear_start: m,
+            linear_length: l,
+            black_tightness: c,
+            pedestal: b,
+        } = *self;
+        let x = x.max(0.0);
+
+        let l0 = (p - m) * l / a;
+        let s0 = m + l0;
+        let s1 = m + a * l0;
+        let c2 = a * p / (p - s1);
+        let cp = -c2 / p;
+
+        let w0 = 1.0 - smoothstep(0.0, m, x);
+        let w2 = if x >= s0 { 1.0 } else { 0.0 };
+        let w1 = 1.0 - w0 - w2;
+
+        let toe = m * (x / m).powf(c) + b;
+        let shoulder = p - (p - s1) * (cp * (x - s0)).exp();
+        let linear = m + a * (x - m);
+
+        toe * w0 + linear * w1 + shoulder * w2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_are_monotonic_and_bounded() {
+        for tone_mapping in [
+            ToneMapping::Reinhard,
+            ToneMapping::ExtendedReinhard { white_point: 4.0 },
+            ToneMapping::Aces,
+            ToneMapping::Uchimura(UchimuraCurve::default()),
+        ] {
+            let mut previous = 0.0;
+            // Up to the white point of the extended curve, past which it exceeds one.
+            for step in 0..=400 {
+                let radiance = step as f64 / 100.0;
+                let mapped = tone_mapping
+                    .apply(Color::new(radiance, radiance, radiance))
+                    .x;
+
+                assert!(mapped >= previous - 1e-12, "{tone_mapping:?} at {radiance}");
+                previous = mapped;
+            }
+            assert!(tone_mapping.apply(Color::zeros()).x.abs() < 1e-12);
+            assert!(
+                previous <= 1.0 + 1e-9,
+                "{tone_mapping:?} reaches {previous}"
+            );
+        }
+
+        let white = ToneMapping::ExtendedReinhard { white_point: 4.0 };
+        assert!((white.apply(Color::new(4.0, 4.0, 4.0)).x - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,92 @@
+use std::ops::{Add, Mul, Sub};
+
+use serde::Deserialize;
+
+/// How values move between keyframes.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum Interpolation {
+    /// Straight from one keyframe to the next, changing speed abruptly at each.
+    #[default]
+    Linear,
+    /// Smooth curve through every keyframe, with the tangent at each one pointing from
+    /// the keyframe before it to the one after it.
+    CatmullRom,
+}
+
+/// Value at `time` of the curve through `keys`, whose time and value `key` returns, in
+/// increasing order of time. Before the first and after the last keyframe the value stays
+/// constant.
+pub fn interpolate<K, T>(
+    interpolation: Interpolation,
+    keys: &[K],
+    key: impl Fn(&K) -> (f64, T),
+    time: f64,
+) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    let after = keys.partition_point(|entry| key(entry).0 <= time);
+    if after == 0 {
+        return key(&keys[0]).1;
+    }
+    if after == keys.len() {
+        return key(&keys[after - 1]).1;
+    }
+    let (start_time, start) = key(&keys[after - 1]);
+    let (end_time, end) = key(&keys[after]);
+    let duration = end_time - start_time;
+    let t = (time - start_time) / duration;
+
+    match interpolation {
+        Interpolation::Linear => start + (end - start) * t,
+        Interpolation::CatmullRom => {
+            // Cubic Hermite segment, with tangents per unit of time so that keyframes need
+            // not be evenly spaced. The outer keyframes use the slope of their segment.
+            let tangent = |index: usize| {
+                let before = key(&keys[index.saturating_sub(1)]);
+                let after = key(&keys[(index + 1).min(keys.len() - 1)]);
+                (after.1 - before.1) * (1.0 / (after.0 - before.0))
+            };
+            let t2 = t * t;
+            let t3 = t2 * t;
+            start * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + tangent(after - 1) * ((t3 - 2.0 * t2 + t) * duration)
+                + end * (3.0 * t2 - 2.0 * t3)
+                + tangent(after) * ((t3 - t2) * duration)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_pass_through_every_keyframe() {
+        let keys = [(0.0, 0.0), (1.0, 2.0), (4.0, 2.0), (5.0, -1.0)];
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom] {
+            for (time, value) in keys {
+                assert!(
+                    (interpolate(interpolation, &keys, |key| *key, time) - value).abs() < 1e-12
+                );
+            }
+            assert_eq!(interpolate(interpolation, &keys, |key| *key, -3.0), 0.0);
+            assert_eq!(interpolate(interpolation, &keys, |key| *key, 9.0), -1.0);
+        }
+
+        assert_eq!(
+            interpolate(Interpolation::Linear, &keys, |key| *key, 0.25),
+            0.5
+        );
+        assert_eq!(
+            interpolate(Interpolation::Linear, &keys, |key| *key, 2.5),
+            2.0
+        );
+        // Between two equal keyframes the spline carries on the rise before them and leads
+        // into the fall after them, so it bulges above both, without kinks at keyframes.
+        let smooth = |time| interpolate(Interpolation::CatmullRom, &keys, |key| *key, time);
+        assert!(smooth(1.5) > 2.0);
+        assert!(smooth(3.5) > 2.0);
+        assert!((smooth(1.0 + 1e-6) - smooth(1.0 - 1e-6)).abs() < 1e-5);
+    }
+}
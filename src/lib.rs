@@ -1,9 +1,16 @@
 mod camera;
+mod checkpoint;
+mod distributed;
+mod film;
 mod helpers;
 mod image;
+mod keyframes;
 mod light;
 mod object;
 pub mod raytracer;
 mod renderer;
+mod sampler;
 mod scene;
 mod shader;
+mod statistics;
+mod texture;
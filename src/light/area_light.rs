@@ -3,6 +3,8 @@ use serde::Deserialize;
 use crate::{
     helpers::{gray_scale, Color, Vec2, Vec3},
     object::{
+        bounding_box::BoundingBox,
+        bvh::Bounded,
         face::{Face, FaceBuilder},
         intersection::Intersectable,
     },
@@ -75,6 +77,12 @@ impl AreaLight {
     }
 }
 
+impl Bounded for AreaLight {
+    fn get_bounding_box(&self) -> &BoundingBox {
+        self.gem.get_bounding_box()
+    }
+}
+
 impl Intersectable for AreaLight {
     fn intersect(
         &self,
@@ -86,4 +94,8 @@ impl Intersectable for AreaLight {
 
         Some(intersection)
     }
+
+    fn occluded(&self, ray: &crate::object::ray::Ray) -> bool {
+        self.gem.occluded(ray)
+    }
 }
@@ -1,17 +1,12 @@
-use fastrand::Rng;
-
-use crate::helpers::Color;
+use crate::{helpers::Color, sampler::Sampler};
 
 use self::base_sampler::BaseSampler;
 
-use super::{
-    area_light::AreaLight, light_sample_context::LightSampleContext, Light, SampleLightResult,
-};
+use super::{light_sample_context::LightSampleContext, Light, SampleLightResult};
 
 mod base_sampler;
 mod cumulative_distribution;
 pub mod power_sampler;
-pub mod uniform_sampler;
 
 pub struct SampleLight {
     pub light: Light,
@@ -24,15 +19,15 @@ pub trait LightSampler: HasBaseSampler {
         self.base_sampler().sample_ambient_lights(ambient_component)
     }
 
-    fn geometric_lights(&self) -> impl Iterator<Item = &AreaLight> {
-        self.base_sampler().geometric_lights()
-    }
-
-    fn sample(&self, context: LightSampleContext, rng: &mut Rng) -> Option<SampleLight> {
-        self.base_sampler().sample(context, rng)
+    fn sample<S: Sampler>(
+        &self,
+        context: LightSampleContext,
+        sampler: &mut S,
+    ) -> Option<SampleLight> {
+        self.base_sampler().sample(context, sampler)
     }
 }
 
 pub trait HasBaseSampler {
-    fn base_sampler(&self) -> &BaseSampler;
+    fn base_sampler(&self) -> &BaseSampler<'_>;
 }
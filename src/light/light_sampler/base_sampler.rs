@@ -1,16 +1,7 @@
-use fastrand::Rng;
-use rand::{
-    distributions::{Distribution, WeightedIndex},
-    rngs::StdRng,
-    SeedableRng,
-};
-
 use crate::{
     helpers::{Color, Vec3},
-    light::{
-        ambient_light::AmbientLight, area_light::AreaLight,
-        light_sample_context::LightSampleContext, Light,
-    },
+    light::{ambient_light::AmbientLight, light_sample_context::LightSampleContext, Light},
+    sampler::Sampler,
 };
 
 use super::{HasBaseSampler, LightSampler, SampleLight};
@@ -48,16 +39,6 @@ impl<'a> BaseSampler<'a> {
 }
 
 impl LightSampler for BaseSampler<'_> {
-    fn geometric_lights(&self) -> impl Iterator<Item = &AreaLight> {
-        self.positional_lights.iter().filter_map(|light| {
-            if let Light::Area(light) = light {
-                Some(light)
-            } else {
-                None
-            }
-        })
-    }
-
     fn sample_ambient_lights(&self, ambient_component: [f32; 3]) -> Color {
         let ambient = [
             ambient_component[0] as f64,
@@ -71,12 +52,24 @@ impl LightSampler for BaseSampler<'_> {
             .sum()
     }
 
-    fn sample(&self, _ctx: LightSampleContext, rng: &mut Rng) -> Option<SampleLight> {
-        let dist = WeightedIndex::new(&self.weights).ok()?;
-        let mut std_rng = StdRng::from_entropy();
+    fn sample<S: Sampler>(&self, _ctx: LightSampleContext, sampler: &mut S) -> Option<SampleLight> {
+        let total: f64 = self.weights.iter().sum();
+        if self.positional_lights.is_empty() || total <= 0.0 {
+            return None;
+        }
+
+        let mut target = sampler.get_1d() * total;
+        let index = self
+            .weights
+            .iter()
+            .position(|weight| {
+                target -= weight;
+                target < 0.0
+            })
+            .unwrap_or(self.weights.len() - 1);
 
-        let light = self.positional_lights[dist.sample(&mut std_rng)].clone();
-        let sample_result = light.l(rng.into());
+        let light = self.positional_lights[index].clone();
+        let sample_result = light.l(sampler);
         Some(SampleLight {
             light,
             power: 1. / self.positional_lights.len() as f64,
@@ -86,7 +79,7 @@ impl LightSampler for BaseSampler<'_> {
 }
 
 impl HasBaseSampler for BaseSampler<'_> {
-    fn base_sampler(&self) -> &BaseSampler {
+    fn base_sampler(&self) -> &BaseSampler<'_> {
         self
     }
 }
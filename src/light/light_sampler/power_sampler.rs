@@ -1,9 +1,10 @@
-use fastrand::Rng;
-
-use crate::light::{light_sample_context::LightSampleContext, Light};
+use crate::{
+    light::{light_sample_context::LightSampleContext, Light},
+    sampler::Sampler,
+};
 
 use super::{
-    base_sampler::BaseSampler, cumulative_distribution::CDF, HasBaseSampler, LightSampler,
+    base_sampler::BaseSampler, cumulative_distribution::Cdf, HasBaseSampler, LightSampler,
     SampleLight,
 };
 
@@ -22,15 +23,17 @@ impl<'a> PowerLightSampler<'a> {
 }
 
 impl LightSampler for PowerLightSampler<'_> {
-    fn sample(&self, context: LightSampleContext, rng: &mut Rng) -> Option<SampleLight> {
+    fn sample<S: Sampler>(
+        &self,
+        context: LightSampleContext,
+        sampler: &mut S,
+    ) -> Option<SampleLight> {
         let mut weights: Vec<_> = self
             .base_sampler
             .positional_lights
             .iter()
             .map(|light| {
-                let sample = light
-                    .l(rng.into())
-                    .calculate_data(&light, context.intersection);
+                let sample = light.l(sampler).calculate_data(light, context.intersection);
                 let cos = sample.cos.unwrap();
                 let distance = sample.distance.unwrap();
                 let power_gs = sample.power_gs;
@@ -45,8 +48,8 @@ impl LightSampler for PowerLightSampler<'_> {
             });
         }
 
-        let dist = CDF::new(&weights);
-        let (index, (sample_result, weight)) = dist.sample(rng)?;
+        let dist = Cdf::new(&weights);
+        let (index, (sample_result, weight)) = dist.sample(sampler.get_1d())?;
         let light = self.base_sampler.positional_lights[index].clone();
         let power = weight;
 
@@ -59,7 +62,7 @@ impl LightSampler for PowerLightSampler<'_> {
 }
 
 impl HasBaseSampler for PowerLightSampler<'_> {
-    fn base_sampler(&self) -> &BaseSampler {
+    fn base_sampler(&self) -> &BaseSampler<'_> {
         &self.base_sampler
     }
 }
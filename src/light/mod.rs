@@ -4,11 +4,11 @@ pub mod light_sample_context;
 pub mod light_sampler;
 pub mod point_light;
 
-use fastrand::Rng;
 use serde::Deserialize;
 
-use crate::helpers::{Color, Vec2, Vec3};
+use crate::helpers::{Color, Vec3};
 use crate::object::intersection::Intersection;
+use crate::sampler::Sampler;
 
 use self::ambient_light::AmbientLight;
 use self::area_light::{AreaLight, AreaLightArgs};
@@ -34,7 +34,7 @@ impl SampleLightResult {
                 let light_distance = light_dir.norm();
                 light_dir.normalize_mut();
 
-                self.cos = light_dir.dot(&intersection.shading_normal()).into();
+                self.cos = light_dir.dot(intersection.shading_normal()).into();
                 self.distance = light_distance.into();
                 self.light_dir = light_dir.into();
             }
@@ -44,7 +44,7 @@ impl SampleLightResult {
                 let mut light_dir = point - i_point;
                 let light_distance = light_dir.norm();
                 light_dir.normalize_mut();
-                let cos_l = light_dir.dot(&intersection.shading_normal());
+                let cos_l = light_dir.dot(intersection.shading_normal());
 
                 let cos_l_la = light_dir.dot(light.normal());
 
@@ -75,6 +75,7 @@ pub enum LightArgs {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Light {
     Ambient(AmbientLight),
     Point(PointLight),
@@ -83,19 +84,12 @@ pub enum Light {
 
 impl Light {
     pub fn is_ambient_light(&self) -> bool {
-        match self {
-            Self::Ambient(_) => true,
-            _ => false,
-        }
+        matches!(self, Self::Ambient(_))
     }
 
-    pub fn l(&self, rng: Option<&mut Rng>) -> SampleLightResult {
+    pub fn l<S: Sampler>(&self, sampler: &mut S) -> SampleLightResult {
         match self {
-            Self::Area(area_light) => {
-                let rng = rng.unwrap();
-                let randoms = Vec2::new(rng.f64(), rng.f64());
-                area_light.l(&randoms)
-            }
+            Self::Area(area_light) => area_light.l(&sampler.get_2d()),
             Self::Point(point_light) => point_light.l(),
             Self::Ambient(ambient_light) => ambient_light.l(),
         }
@@ -1,4 +1,6 @@
-use clap::Parser;
+use std::ops::Range;
+
+use clap::{Parser, Subcommand};
 use raytracer_lib::raytracer::{Configuration, RayTracer};
 
 #[derive(Debug, Parser)]
@@ -6,13 +8,58 @@ use raytracer_lib::raytracer::{Configuration, RayTracer};
 struct Args {
     #[arg(short, long, default_value = "configuration.json")]
     configuration: String,
+    /// Continue the render saved in the configured checkpoint file.
+    #[arg(long)]
+    resume: bool,
+    /// Renders the whole image in this process when omitted.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Render part of the image, for a coordinator or into a partial file.
+    Worker {
+        /// Address of the coordinator to take assignments from.
+        #[arg(long, conflicts_with_all = ["samples", "tiles", "output"])]
+        connect: Option<String>,
+        /// Sample indices to render, as START..END; all of them by default.
+        #[arg(long, value_parser = parse_range)]
+        samples: Option<Range<usize>>,
+        /// Indices of the tiles to render, in render order, as START..END; all of them by
+        /// default.
+        #[arg(long, value_parser = parse_range)]
+        tiles: Option<Range<usize>>,
+        /// File the partial render is saved to.
+        #[arg(long, required_unless_present = "connect")]
+        output: Option<String>,
+    },
+    /// Hand out the image to workers over TCP and save the merged result.
+    Coordinate {
+        /// Address to listen for workers on.
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        listen: String,
+    },
+    /// Add up partial renders into the output image.
+    Merge {
+        #[arg(required = true)]
+        partials: Vec<String>,
+    },
+}
+
+fn parse_range(range: &str) -> Result<Range<usize>, String> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| format!("expected START..END, got {range}"))?;
+    let parse = |bound: &str| bound.parse::<usize>().map_err(|error| error.to_string());
+    Ok(parse(start)?..parse(end)?)
 }
 
 fn load_configuration(configuration_path: &str) -> Configuration {
     let file = std::fs::File::open(configuration_path).expect("Configuration file does not exist");
     let reader = std::io::BufReader::new(file);
-    serde_json::from_reader::<std::io::BufReader<std::fs::File>, Configuration>(reader)
-        .expect("Error loading Configuration file")
+    let value = serde_json::from_reader(reader).expect("Configuration file is not valid JSON");
+    Configuration::from_json(value).expect("Error loading Configuration file")
 }
 
 fn main() -> anyhow::Result<()> {
@@ -20,7 +67,26 @@ fn main() -> anyhow::Result<()> {
 
     let configuration = load_configuration(&args.configuration);
     let output_file = &configuration.output_file.clone();
-    RayTracer::with_configuration(configuration)?.render(output_file);
+    let mut ray_tracer = RayTracer::with_configuration(configuration)?;
+    for warning in ray_tracer.warnings() {
+        eprintln!("Warning: {warning}");
+    }
+
+    match args.command {
+        None => ray_tracer.render(output_file, args.resume)?,
+        Some(Command::Worker {
+            connect: Some(address),
+            ..
+        }) => ray_tracer.work(&address)?,
+        Some(Command::Worker {
+            samples,
+            tiles,
+            output,
+            ..
+        }) => ray_tracer.render_partial(samples, tiles, &output.unwrap())?,
+        Some(Command::Coordinate { listen }) => ray_tracer.coordinate(&listen, output_file)?,
+        Some(Command::Merge { partials }) => ray_tracer.merge(&partials, output_file)?,
+    }
 
     Ok(())
 }
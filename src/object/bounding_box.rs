@@ -1,6 +1,6 @@
 use serde::Deserialize;
 
-use crate::helpers::Vec3;
+use crate::helpers::{Comparable, Vec3};
 
 use super::ray::Ray;
 
@@ -13,8 +13,16 @@ pub struct BoundingBox {
 impl BoundingBox {
     pub fn new(min: &Vec3, max: &Vec3) -> Self {
         Self {
-            min: min.clone(),
-            max: max.clone(),
+            min: *min,
+            max: *max,
+        }
+    }
+
+    /// Box that contains nothing, so that any union with it yields the other operand.
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3::repeat(f64::INFINITY),
+            max: Vec3::repeat(f64::NEG_INFINITY),
         }
     }
 
@@ -22,7 +30,59 @@ impl BoundingBox {
         (&self.min, &self.max)
     }
 
+    pub fn union(&self, other: &BoundingBox) -> Self {
+        Self {
+            min: self.min.min_between(&other.min),
+            max: self.max.max_between(&other.max),
+        }
+    }
+
+    pub fn extend(&self, point: &Vec3) -> Self {
+        Self {
+            min: self.min.min_between(point),
+            max: self.max.max_between(point),
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let extent = self.max - self.min;
+        if extent.x < 0.0 || extent.y < 0.0 || extent.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    pub fn largest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
     pub fn intersect(&self, ray: &Ray) -> bool {
+        let (t_min, t_max) = self.slabs(ray);
+
+        t_max >= t_min
+    }
+
+    /// Distance along the ray at which it enters the box, if it does so before `t_max`,
+    /// and no closer than the start of the ray's interval.
+    pub fn hit_distance(&self, ray: &Ray, t_max: f64) -> Option<f64> {
+        let (t_enter, t_exit) = self.slabs(ray);
+        let t_enter = t_enter.max(ray.t_min());
+
+        (t_exit >= t_enter && t_enter <= t_max).then_some(t_enter)
+    }
+
+    fn slabs(&self, ray: &Ray) -> (f64, f64) {
         let origin = ray.origin();
         let direction = ray.direction();
 
@@ -44,6 +104,6 @@ impl BoundingBox {
         let t_min = t_min.max(t_z_min.min(t_z_max));
         let t_max = t_max.min(t_z_min.max(t_z_max));
 
-        t_max >= t_min
+        (t_min, t_max)
     }
 }
@@ -0,0 +1,348 @@
+use serde::Deserialize;
+
+use crate::{helpers::Vec3, statistics};
+
+use super::{
+    bounding_box::BoundingBox,
+    intersection::{Intersectable, Intersection},
+    ray::Ray,
+};
+
+const BUCKET_COUNT: usize = 12;
+const TRAVERSAL_COST: f64 = 0.125;
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BvhArgs {
+    #[serde(default = "default_max_leaf_size")]
+    pub max_leaf_size: usize,
+}
+
+fn default_max_leaf_size() -> usize {
+    4
+}
+
+impl Default for BvhArgs {
+    fn default() -> Self {
+        Self {
+            max_leaf_size: default_max_leaf_size(),
+        }
+    }
+}
+
+pub trait Bounded {
+    fn get_bounding_box(&self) -> &BoundingBox;
+}
+
+#[derive(Debug, Clone)]
+enum BvhNode {
+    /// The first child is always stored right after its parent.
+    Interior {
+        bounding_box: BoundingBox,
+        second_child: usize,
+    },
+    Leaf {
+        bounding_box: BoundingBox,
+        first_primitive: usize,
+        primitive_count: usize,
+    },
+}
+
+impl BvhNode {
+    fn bounding_box(&self) -> &BoundingBox {
+        match self {
+            Self::Interior { bounding_box, .. } | Self::Leaf { bounding_box, .. } => bounding_box,
+        }
+    }
+}
+
+struct BuildPrimitive {
+    index: usize,
+    bounding_box: BoundingBox,
+    centroid: Vec3,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy with a binned SAH split and reorders `primitives` so that
+    /// every leaf references a contiguous range of them.
+    pub fn new<T: Bounded>(primitives: &mut Vec<T>, args: &BvhArgs) -> Self {
+        let mut build_primitives: Vec<BuildPrimitive> = primitives
+            .iter()
+            .enumerate()
+            .map(|(index, primitive)| {
+                let bounding_box = primitive.get_bounding_box().clone();
+                BuildPrimitive {
+                    index,
+                    centroid: bounding_box.centroid(),
+                    bounding_box,
+                }
+            })
+            .collect();
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * primitives.len()),
+        };
+        if !build_primitives.is_empty() {
+            bvh.build(&mut build_primitives, 0, args.max_leaf_size.max(1), 0);
+        }
+
+        let mut taken: Vec<Option<T>> = primitives.drain(..).map(Some).collect();
+        primitives.extend(
+            build_primitives
+                .iter()
+                .map(|primitive| taken[primitive.index].take().expect("primitive moved once")),
+        );
+
+        bvh
+    }
+
+    fn build(
+        &mut self,
+        primitives: &mut [BuildPrimitive],
+        offset: usize,
+        max_leaf_size: usize,
+        depth: usize,
+    ) -> usize {
+        let bounding_box = primitives
+            .iter()
+            .fold(BoundingBox::empty(), |acc, primitive| {
+                acc.union(&primitive.bounding_box)
+            });
+        let node_index = self.nodes.len();
+        let leaf = BvhNode::Leaf {
+            bounding_box: bounding_box.clone(),
+            first_primitive: offset,
+            primitive_count: primitives.len(),
+        };
+
+        if primitives.len() == 1 || depth >= MAX_DEPTH {
+            self.nodes.push(leaf);
+            return node_index;
+        }
+
+        let Some(mid) = Self::split(primitives, &bounding_box, max_leaf_size) else {
+            self.nodes.push(leaf);
+            return node_index;
+        };
+
+        self.nodes.push(BvhNode::Interior {
+            bounding_box,
+            second_child: 0,
+        });
+        let (left, right) = primitives.split_at_mut(mid);
+        self.build(left, offset, max_leaf_size, depth + 1);
+        let second = self.build(right, offset + mid, max_leaf_size, depth + 1);
+        if let BvhNode::Interior { second_child, .. } = &mut self.nodes[node_index] {
+            *second_child = second;
+        }
+
+        node_index
+    }
+
+    /// Partitions `primitives` along the cheapest SAH bucket boundary and returns the
+    /// split position, or `None` when keeping them in a single leaf is cheaper.
+    fn split(
+        primitives: &mut [BuildPrimitive],
+        bounding_box: &BoundingBox,
+        max_leaf_size: usize,
+    ) -> Option<usize> {
+        let centroid_box = primitives
+            .iter()
+            .fold(BoundingBox::empty(), |acc, primitive| {
+                acc.extend(&primitive.centroid)
+            });
+        let axis = centroid_box.largest_axis();
+        let (min, max) = centroid_box.get_min_max();
+        let (min, extent) = (min[axis], max[axis] - min[axis]);
+
+        if extent <= 0.0 {
+            if primitives.len() <= max_leaf_size {
+                return None;
+            }
+            return Some(split_in_half(primitives, axis));
+        }
+
+        let bucket_of = |primitive: &BuildPrimitive| {
+            let bucket = (BUCKET_COUNT as f64 * (primitive.centroid[axis] - min) / extent) as usize;
+            bucket.min(BUCKET_COUNT - 1)
+        };
+
+        let mut counts = [0usize; BUCKET_COUNT];
+        let mut boxes: [BoundingBox; BUCKET_COUNT] = std::array::from_fn(|_| BoundingBox::empty());
+        for primitive in primitives.iter() {
+            let bucket = bucket_of(primitive);
+            counts[bucket] += 1;
+            boxes[bucket] = boxes[bucket].union(&primitive.bounding_box);
+        }
+
+        let mut costs = [0.0; BUCKET_COUNT - 1];
+        let (mut count_below, mut box_below) = (0, BoundingBox::empty());
+        for split in 0..BUCKET_COUNT - 1 {
+            count_below += counts[split];
+            box_below = box_below.union(&boxes[split]);
+            costs[split] = count_below as f64 * box_below.surface_area();
+        }
+        let (mut count_above, mut box_above) = (0, BoundingBox::empty());
+        for split in (0..BUCKET_COUNT - 1).rev() {
+            count_above += counts[split + 1];
+            box_above = box_above.union(&boxes[split + 1]);
+            costs[split] += count_above as f64 * box_above.surface_area();
+        }
+
+        let (best_split, best_cost) = costs
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(split, cost)| (split, *cost))?;
+
+        let area = bounding_box.surface_area();
+        let split_cost = if area > 0.0 {
+            TRAVERSAL_COST + best_cost / area
+        } else {
+            TRAVERSAL_COST + primitives.len() as f64
+        };
+        let leaf_cost = primitives.len() as f64;
+
+        if primitives.len() <= max_leaf_size && leaf_cost <= split_cost {
+            return None;
+        }
+
+        let mid = partition(primitives, |primitive| bucket_of(primitive) <= best_split);
+        if mid == 0 || mid == primitives.len() {
+            Some(split_in_half(primitives, axis))
+        } else {
+            Some(mid)
+        }
+    }
+
+    /// Closest hit, visiting children front-to-back and skipping every node that starts
+    /// beyond the closest intersection found so far.
+    pub fn intersect<T: Intersectable>(&self, ray: &Ray, primitives: &[T]) -> Option<Intersection> {
+        let root = self.nodes.first()?;
+        let mut t_max = ray.t_max();
+        let mut closest = None;
+
+        let mut stack = [(0usize, 0.0f64); MAX_DEPTH + 1];
+        stack[0] = (0, root.bounding_box().hit_distance(ray, t_max)?);
+        let mut stack_size = 1;
+        let mut visits = 0;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let (node_index, t_near) = stack[stack_size];
+            if t_near > t_max {
+                continue;
+            }
+            visits += 1;
+
+            match &self.nodes[node_index] {
+                BvhNode::Leaf {
+                    first_primitive,
+                    primitive_count,
+                    ..
+                } => {
+                    for primitive in
+                        &primitives[*first_primitive..first_primitive + primitive_count]
+                    {
+                        if let Some(intersection) = primitive.intersect(ray) {
+                            if intersection.depth() < t_max {
+                                t_max = intersection.depth();
+                                closest = Some(intersection);
+                            }
+                        }
+                    }
+                }
+                BvhNode::Interior { second_child, .. } => {
+                    let first = node_index + 1;
+                    let t_first = self.nodes[first].bounding_box().hit_distance(ray, t_max);
+                    let t_second = self.nodes[*second_child]
+                        .bounding_box()
+                        .hit_distance(ray, t_max);
+
+                    let mut children = [(first, t_first), (*second_child, t_second)];
+                    if t_first.unwrap_or(f64::INFINITY) < t_second.unwrap_or(f64::INFINITY) {
+                        children.swap(0, 1);
+                    }
+                    for (child, t_child) in children {
+                        if let Some(t_child) = t_child {
+                            stack[stack_size] = (child, t_child);
+                            stack_size += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        statistics::count(|counts| counts.bvh_node_visits += visits);
+        closest
+    }
+
+    /// Any hit inside the ray's interval, returning as soon as one primitive reports it.
+    pub fn occluded<T: Intersectable>(&self, ray: &Ray, primitives: &[T]) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let mut stack = [0usize; MAX_DEPTH + 1];
+        let mut stack_size = 1;
+        let mut visits = 0;
+        let count_visits = |visits| statistics::count(|counts| counts.bvh_node_visits += visits);
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node_index = stack[stack_size];
+            let node = &self.nodes[node_index];
+            if node.bounding_box().hit_distance(ray, ray.t_max()).is_none() {
+                continue;
+            }
+            visits += 1;
+
+            match node {
+                BvhNode::Leaf {
+                    first_primitive,
+                    primitive_count,
+                    ..
+                } => {
+                    if primitives[*first_primitive..first_primitive + primitive_count]
+                        .iter()
+                        .any(|primitive| primitive.occluded(ray))
+                    {
+                        count_visits(visits);
+                        return true;
+                    }
+                }
+                BvhNode::Interior { second_child, .. } => {
+                    stack[stack_size] = *second_child;
+                    stack[stack_size + 1] = node_index + 1;
+                    stack_size += 2;
+                }
+            }
+        }
+
+        count_visits(visits);
+        false
+    }
+}
+
+fn split_in_half(primitives: &mut [BuildPrimitive], axis: usize) -> usize {
+    let mid = primitives.len() / 2;
+    primitives.select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+    mid
+}
+
+/// Moves every element matching `predicate` to the front and returns how many there are.
+fn partition<T>(items: &mut [T], predicate: impl Fn(&T) -> bool) -> usize {
+    let mut first_false = 0;
+    for index in 0..items.len() {
+        if predicate(&items[index]) {
+            items.swap(first_false, index);
+            first_false += 1;
+        }
+    }
+    first_false
+}
@@ -1,10 +1,11 @@
 use nalgebra::Vector3;
 use serde::Deserialize;
 
-use crate::helpers::{Rotateable, Vec3};
+use crate::helpers::{CoordinateSystemProvider, Rotateable, Vec2, Vec3};
 
 use super::{
     bounding_box::BoundingBox,
+    bvh::Bounded,
     intersection::{Intersectable, Intersection},
     ray::Ray,
 };
@@ -16,6 +17,8 @@ pub struct FaceBuilder {
     face_id: Option<usize>,
     vertex: [Vec3; 3],
     normal: Option<Vec3>,
+    vertex_normals: Option<[Vec3; 3]>,
+    texcoords: Option<[Vec2; 3]>,
 }
 
 impl FaceBuilder {
@@ -31,6 +34,16 @@ impl FaceBuilder {
         self
     }
 
+    pub fn vertex_normals(mut self, vertex_normals: [Vec3; 3]) -> Self {
+        self.vertex_normals = Some(vertex_normals);
+        self
+    }
+
+    pub fn texcoords(mut self, texcoords: [Vec2; 3]) -> Self {
+        self.texcoords = Some(texcoords);
+        self
+    }
+
     pub fn face_id(mut self, face_id: usize) -> Self {
         self.face_id = Some(face_id);
         self
@@ -43,7 +56,15 @@ impl FaceBuilder {
 
 impl From<FaceBuilder> for Face {
     fn from(value: FaceBuilder) -> Self {
-        Self::new(value.vertex, value.normal)
+        let face = Self::new(value.vertex, value.normal);
+        Self {
+            vertex_normals: value.vertex_normals,
+            texcoords: value.texcoords,
+            uv_derivatives: value
+                .texcoords
+                .map(|texcoords| face.uv_derivatives(&texcoords)),
+            ..face
+        }
     }
 }
 
@@ -51,6 +72,11 @@ impl From<FaceBuilder> for Face {
 pub struct Face {
     vertex: [Vec3; 3],
     normal: Vec3,
+    /// Per-vertex normals interpolated into the shading normal; flat shading when absent.
+    vertex_normals: Option<[Vec3; 3]>,
+    texcoords: Option<[Vec2; 3]>,
+    /// Partial derivatives `(dp/du, dp/dv)` of the surface over the texture coordinates.
+    uv_derivatives: Option<(Vec3, Vec3)>,
     bounding_box: BoundingBox,
     area: f64,
 }
@@ -91,6 +117,9 @@ impl Face {
         Self {
             vertex,
             normal,
+            vertex_normals: None,
+            texcoords: None,
+            uv_derivatives: None,
             bounding_box,
             area,
         }
@@ -104,17 +133,48 @@ impl Face {
         &self.vertex
     }
 
-    pub fn get_bounding_box(&self) -> &BoundingBox {
-        &self.bounding_box
-    }
-
     pub fn area(&self) -> f64 {
         self.area
     }
-}
 
-impl Intersectable for Face {
-    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    fn shading_normal(&self, u: f64, v: f64, geometric_normal: &Vec3) -> Vec3 {
+        let Some(normals) = &self.vertex_normals else {
+            return *geometric_normal;
+        };
+
+        let normal = ((1.0 - u - v) * normals[0] + u * normals[1] + v * normals[2])
+            .try_normalize(f64::EPSILON)
+            .unwrap_or(*geometric_normal);
+        normal.face_forward(geometric_normal)
+    }
+
+    fn texcoord(&self, u: f64, v: f64) -> Option<Vec2> {
+        self.texcoords
+            .map(|uv| (1.0 - u - v) * uv[0] + u * uv[1] + v * uv[2])
+    }
+
+    /// Solves the edge equations `dp = du * dp/du + dv * dp/dv`, falling back to an
+    /// arbitrary frame around the normal when the mapping is degenerate.
+    fn uv_derivatives(&self, texcoords: &[Vec2; 3]) -> (Vec3, Vec3) {
+        let duv_02 = texcoords[0] - texcoords[2];
+        let duv_12 = texcoords[1] - texcoords[2];
+        let dp_02 = self.vertex[0] - self.vertex[2];
+        let dp_12 = self.vertex[1] - self.vertex[2];
+
+        let det = duv_02.x * duv_12.y - duv_02.y * duv_12.x;
+        if det.abs() < f64::EPSILON {
+            return self.normal.coordinate_system();
+        }
+
+        let inv_det = 1.0 / det;
+        (
+            (duv_12.y * dp_02 - duv_02.y * dp_12) * inv_det,
+            (duv_02.x * dp_12 - duv_12.x * dp_02) * inv_det,
+        )
+    }
+
+    /// Distance and barycentric coordinates `(t, u, v)` of the hit, if any.
+    fn hit(&self, ray: &Ray) -> Option<(f64, f64, f64)> {
         if !self.bounding_box.intersect(ray) {
             return None;
         }
@@ -143,21 +203,93 @@ impl Intersectable for Face {
 
         let t = inv_det * edge_2.dot(&s_cross_e1);
 
-        if t > EPSILON {
-            let intersection_point = ray.origin() + ray.direction() * t;
-            let wo = -1.0 * ray.direction();
-
-            let normal = self.normal.face_forward(&wo);
-            Some(Intersection::new(
-                intersection_point,
-                normal,
-                normal,
-                wo,
-                t,
-                None,
-            ))
-        } else {
-            None
-        }
+        (t > EPSILON && ray.contains(t)).then_some((t, u, v))
+    }
+}
+
+impl Bounded for Face {
+    fn get_bounding_box(&self) -> &BoundingBox {
+        &self.bounding_box
+    }
+}
+
+impl Intersectable for Face {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (t, u, v) = self.hit(ray)?;
+
+        let intersection_point = ray.origin() + ray.direction() * t;
+        let wo = -1.0 * ray.direction();
+
+        let normal = self.normal.face_forward(&wo);
+        let intersection = Intersection::new(
+            intersection_point,
+            normal,
+            self.shading_normal(u, v, &normal),
+            wo,
+            t,
+            None,
+        );
+        Some(match (self.texcoord(u, v), self.uv_derivatives) {
+            (Some(uv), Some((dpdu, dpdv))) => intersection.with_uv(uv, dpdu, dpdv),
+            _ => intersection,
+        })
+    }
+
+    fn occluded(&self, ray: &Ray) -> bool {
+        self.hit(ray).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> FaceBuilder {
+        FaceBuilder::new([Vec3::zeros(), Vec3::x(), Vec3::y()])
+    }
+
+    fn hit(face: &Face, x: f64, y: f64) -> Intersection {
+        face.intersect(&Ray::new(&Vec3::new(x, y, 1.0), &-Vec3::z()))
+            .unwrap()
+    }
+
+    #[test]
+    fn shading_normals_interpolate_the_vertex_normals() {
+        let normals = [
+            Vec3::z(),
+            Vec3::new(1.0, 0.0, 1.0).normalize(),
+            Vec3::new(0.0, 1.0, 1.0).normalize(),
+        ];
+        let smooth = triangle().vertex_normals(normals).build();
+
+        let expected = (0.25 * normals[0] + 0.25 * normals[1] + 0.5 * normals[2]).normalize();
+        let intersection = hit(&smooth, 0.25, 0.5);
+        assert!((intersection.shading_normal() - expected).norm() < 1e-12);
+        assert_eq!(intersection.geometric_normal(), &Vec3::z());
+        assert!((hit(&smooth, 1e-9, 1e-9).shading_normal() - normals[0]).norm() < 1e-6);
+
+        // Flat faces shade with the geometric normal.
+        assert_eq!(
+            hit(&triangle().build(), 0.25, 0.5).shading_normal(),
+            &Vec3::z()
+        );
+    }
+
+    #[test]
+    fn texture_coordinates_interpolate_over_the_face() {
+        let textured = triangle()
+            .texcoords([
+                Vec2::new(0.5, 0.5),
+                Vec2::new(1.5, 0.5),
+                Vec2::new(0.5, 0.0),
+            ])
+            .build();
+
+        let intersection = hit(&textured, 0.25, 0.5);
+        assert!((intersection.uv().unwrap() - Vec2::new(0.75, 0.25)).norm() < 1e-12);
+        // u runs along x, v against y at half the rate.
+        assert!((intersection.dpdu() - Vec3::x()).norm() < 1e-12);
+        assert!((intersection.dpdv() - -2.0 * Vec3::y()).norm() < 1e-12);
+        assert!(hit(&triangle().build(), 0.25, 0.5).uv().is_none());
     }
 }
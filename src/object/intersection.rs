@@ -1,8 +1,8 @@
 use tobj::Material;
 
-use crate::helpers::{Color, Vec3};
+use crate::helpers::{Color, Rotateable, Vec2, Vec3};
 
-use super::ray::Ray;
+use super::{ray::Ray, transform::Transform};
 
 #[derive(Debug, Clone)]
 pub struct MaterialInformation {
@@ -17,9 +17,17 @@ pub struct Intersection {
     shading_normal: Vec3,
     w_outgoing: Vec3,
     depth: f64,
+    /// Interpolated texture coordinates, when the mesh provides them.
+    uv: Option<Vec2>,
+    dpdu: Vec3,
+    dpdv: Vec3,
     pub brdf: Option<MaterialInformation>,
+    /// Index of the OBJ model that was hit; `None` for lights.
+    pub object_id: Option<usize>,
     /// only used if this is an intersection with a light
     pub light_intensity: Option<Color>,
+    /// Time of the ray that hit, which the rays leaving this point keep.
+    time: f64,
 }
 
 impl Intersection {
@@ -37,11 +45,48 @@ impl Intersection {
             shading_normal,
             w_outgoing,
             depth,
+            uv: None,
+            dpdu: Vec3::zeros(),
+            dpdv: Vec3::zeros(),
             brdf: None,
+            object_id: None,
             light_intensity,
+            time: 0.0,
         }
     }
 
+    pub fn with_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+
+    /// Moves a hit found in the coordinates of an object into the scene by `transform`,
+    /// seen from `w_outgoing` there.
+    pub fn transformed(mut self, transform: &Transform, w_outgoing: Vec3) -> Self {
+        self.point = transform.point(&self.point);
+        self.geometry_normal = transform.normal(&self.geometry_normal);
+        self.shading_normal = transform.normal(&self.shading_normal);
+        self.dpdu = transform.vector(&self.dpdu);
+        self.dpdv = transform.vector(&self.dpdv);
+        self.w_outgoing = w_outgoing;
+        self
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn with_uv(mut self, uv: Vec2, dpdu: Vec3, dpdv: Vec3) -> Self {
+        self.uv = Some(uv);
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self
+    }
+
+    pub fn uv(&self) -> Option<&Vec2> {
+        self.uv.as_ref()
+    }
+
     pub fn brdf(&self) -> Option<&Material> {
         let info = self.brdf.as_ref()?;
         info.material.as_ref()
@@ -70,10 +115,29 @@ impl Intersection {
     pub fn geometric_normal(&self) -> &Vec3 {
         &self.geometry_normal
     }
+
+    pub fn dpdu(&self) -> &Vec3 {
+        &self.dpdu
+    }
+
+    pub fn dpdv(&self) -> &Vec3 {
+        &self.dpdv
+    }
+
+    /// Replaces the shading normal, keeping it on the same side as the geometric one.
+    pub fn set_shading_normal(&mut self, shading_normal: Vec3) {
+        self.shading_normal = shading_normal.face_forward(&self.geometry_normal);
+    }
 }
 
 pub trait Intersectable {
     fn intersect(&self, ray: &Ray) -> Option<Intersection>;
+
+    /// Whether anything is hit inside the ray's interval, without building an
+    /// [`Intersection`] or looking for the closest hit.
+    fn occluded(&self, ray: &Ray) -> bool {
+        self.intersect(ray).is_some()
+    }
 }
 
 pub fn get_min_intersection<'a, T: Intersectable + 'a>(
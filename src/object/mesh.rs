@@ -1,18 +1,36 @@
 use super::{
+    bvh::{Bounded, Bvh, BvhArgs},
     face::FaceBuilder,
-    intersection::{get_min_intersection, Intersectable, MaterialInformation},
+    intersection::{Intersectable, MaterialInformation},
 };
-use nalgebra::Vector3;
+use std::collections::HashMap;
+
 use tobj::Model;
 
 use super::{bounding_box::BoundingBox, face::Face, intersection::Intersection, ray::Ray};
-use crate::helpers::Comparable;
+use crate::helpers::{Comparable, Vec2, Vec3};
+
+fn vec3_at(data: &[f32], index: u32) -> Vec3 {
+    let index = index as usize * 3;
+    Vec3::new(
+        data[index] as f64,
+        data[index + 1] as f64,
+        data[index + 2] as f64,
+    )
+}
+
+fn vec2_at(data: &[f32], index: u32) -> Vec2 {
+    let index = index as usize * 2;
+    Vec2::new(data[index] as f64, data[index + 1] as f64)
+}
 
 #[derive(Debug, Default)]
 pub struct Mesh {
     material_id: Option<usize>,
+    object_id: Option<usize>,
     faces: Vec<Face>,
     bounding_box: BoundingBox,
+    bvh: Bvh,
 }
 
 impl Intersectable for Mesh {
@@ -21,7 +39,10 @@ impl Intersectable for Mesh {
             return None;
         }
 
-        let mut intersection = get_min_intersection(ray, self.faces.iter());
+        let mut intersection = self.bvh.intersect(ray, &self.faces);
+        if let Some(intersection) = &mut intersection {
+            intersection.object_id = self.object_id;
+        }
         if let Some(material_id) = self.material_id {
             if let Some(intersection) = &mut intersection {
                 intersection.brdf = Some(MaterialInformation {
@@ -33,11 +54,144 @@ impl Intersectable for Mesh {
 
         intersection
     }
+
+    fn occluded(&self, ray: &Ray) -> bool {
+        self.bounding_box.intersect(ray) && self.bvh.occluded(ray, &self.faces)
+    }
 }
 
 impl Mesh {
+    pub fn with_object_id(mut self, object_id: usize) -> Self {
+        self.object_id = Some(object_id);
+        self
+    }
+
+    /// `smoothing_groups` holds the group of each triangle of this model and is only used
+    /// to generate vertex normals when the OBJ file does not provide them.
+    pub fn new(model: Model, smoothing_groups: Option<&[u32]>, bvh_args: &BvhArgs) -> Self {
+        let mesh = &model.mesh;
+        let face_count = mesh.indices.len() / 3;
+
+        let mut obj = Self {
+            material_id: mesh.material_id,
+            ..Default::default()
+        };
+
+        let triangles: Vec<[Vec3; 3]> = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|face_indices| {
+                [
+                    vec3_at(&mesh.positions, face_indices[0]),
+                    vec3_at(&mesh.positions, face_indices[1]),
+                    vec3_at(&mesh.positions, face_indices[2]),
+                ]
+            })
+            .collect();
+
+        let vertex_normals: Vec<Option<[Vec3; 3]>> = if !mesh.normals.is_empty() {
+            mesh.indices
+                .chunks_exact(3)
+                .map(|face_indices| {
+                    Some([
+                        vec3_at(&mesh.normals, face_indices[0]),
+                        vec3_at(&mesh.normals, face_indices[1]),
+                        vec3_at(&mesh.normals, face_indices[2]),
+                    ])
+                })
+                .collect()
+        } else if let Some(groups) = smoothing_groups.filter(|groups| groups.len() == face_count) {
+            Self::smooth_normals(&triangles, groups)
+        } else {
+            vec![None; face_count]
+        };
+
+        let has_texcoords =
+            !mesh.texcoords.is_empty() && mesh.texcoords.len() / 2 == mesh.positions.len() / 3;
+        let texcoords: Vec<Option<[Vec2; 3]>> = if has_texcoords {
+            mesh.indices
+                .chunks_exact(3)
+                .map(|face_indices| {
+                    Some([
+                        vec2_at(&mesh.texcoords, face_indices[0]),
+                        vec2_at(&mesh.texcoords, face_indices[1]),
+                        vec2_at(&mesh.texcoords, face_indices[2]),
+                    ])
+                })
+                .collect()
+        } else {
+            vec![None; face_count]
+        };
+
+        obj.faces.reserve(face_count);
+        for (face, ((vertices, normals), texcoords)) in triangles
+            .into_iter()
+            .zip(vertex_normals)
+            .zip(texcoords)
+            .enumerate()
+        {
+            let mut builder = FaceBuilder::new(vertices).face_id(face);
+            if let Some(normals) = normals {
+                builder = builder.vertex_normals(normals);
+            }
+            if let Some(texcoords) = texcoords {
+                builder = builder.texcoords(texcoords);
+            }
+            obj.faces.push(builder.build());
+        }
+
+        obj.update_bounding_box();
+        obj.bvh = Bvh::new(&mut obj.faces, bvh_args);
+        obj
+    }
+
+    /// Area-weighted vertex normals, averaged only across triangles that share both the
+    /// vertex position and the smoothing group. Triangles outside any group stay flat.
+    fn smooth_normals(triangles: &[[Vec3; 3]], groups: &[u32]) -> Vec<Option<[Vec3; 3]>> {
+        let key = |position: &Vec3, group: u32| {
+            (
+                position.x.to_bits(),
+                position.y.to_bits(),
+                position.z.to_bits(),
+                group,
+            )
+        };
+
+        let mut normal_sums: HashMap<_, Vec3> = HashMap::new();
+        for (triangle, &group) in triangles.iter().zip(groups) {
+            if group == 0 {
+                continue;
+            }
+            let face_normal = (triangle[1] - triangle[0]).cross(&(triangle[2] - triangle[0]));
+            for position in triangle {
+                *normal_sums.entry(key(position, group)).or_default() += face_normal;
+            }
+        }
+
+        triangles
+            .iter()
+            .zip(groups)
+            .map(|(triangle, &group)| {
+                (group != 0).then(|| {
+                    triangle.map(|position| {
+                        normal_sums[&key(&position, group)]
+                            .try_normalize(f64::EPSILON)
+                            .unwrap_or_default()
+                    })
+                })
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
     fn update_bounding_box(&mut self) {
-        let (&(mut min_vert), &(mut max_vert)) = self.faces[0].get_bounding_box().get_min_max();
+        let Some(first_face) = self.faces.first() else {
+            return;
+        };
+        let (&(mut min_vert), &(mut max_vert)) = first_face.get_bounding_box().get_min_max();
 
         for face in self.faces.iter().skip(1) {
             let (face_min_vert, face_max_vert) = face.get_bounding_box().get_min_max();
@@ -49,47 +203,118 @@ impl Mesh {
     }
 }
 
-impl From<Model> for Mesh {
-    fn from(model: Model) -> Self {
-        let indices = &model.mesh.indices;
-        let mesh = &model.mesh;
+impl Bounded for Mesh {
+    fn get_bounding_box(&self) -> &BoundingBox {
+        &self.bounding_box
+    }
+}
 
-        let mut obj = Self {
-            material_id: mesh.material_id,
-            ..Default::default()
-        };
+#[cfg(test)]
+mod tests {
+    use tobj::GPU_LOAD_OPTIONS;
+
+    use super::*;
+    use crate::object::{intersection::get_min_intersection, smoothing_groups};
 
-        obj.faces.reserve(mesh.indices.len() / 3);
-        let mut next_face = 0;
-        for face in 0..mesh.indices.len() / 3 {
-            let end = next_face + 3;
-            let face_indices = &indices[next_face..end];
-
-            let vertices = [
-                Vector3::new(
-                    mesh.positions[(face_indices[0] * 3) as usize] as f64,
-                    mesh.positions[(face_indices[0] * 3 + 1) as usize] as f64,
-                    mesh.positions[(face_indices[0] * 3 + 2) as usize] as f64,
-                ),
-                Vector3::new(
-                    mesh.positions[(face_indices[1] * 3) as usize] as f64,
-                    mesh.positions[(face_indices[1] * 3 + 1) as usize] as f64,
-                    mesh.positions[(face_indices[1] * 3 + 2) as usize] as f64,
-                ),
-                Vector3::new(
-                    mesh.positions[(face_indices[2] * 3) as usize] as f64,
-                    mesh.positions[(face_indices[2] * 3 + 1) as usize] as f64,
-                    mesh.positions[(face_indices[2] * 3 + 2) as usize] as f64,
-                ),
-            ];
-
-            obj.faces
-                .push(FaceBuilder::new(vertices).face_id(face).build());
-
-            next_face = end;
+    fn random_point(rng: &mut fastrand::Rng, bounding_box: &BoundingBox) -> Vec3 {
+        let (min, max) = bounding_box.get_min_max();
+        let extent = max - min;
+        let margin = extent * 0.25;
+        Vec3::new(
+            min.x - margin.x + rng.f64() * (extent.x + 2.0 * margin.x),
+            min.y - margin.y + rng.f64() * (extent.y + 2.0 * margin.y),
+            min.z - margin.z + rng.f64() * (extent.z + 2.0 * margin.z),
+        )
+    }
+
+    #[test]
+    fn bvh_matches_brute_force_on_bundled_models() {
+        let mut rng = fastrand::Rng::with_seed(7);
+        let mut meshes_checked = 0;
+
+        for entry in std::fs::read_dir("models").expect("models directory") {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("obj") {
+                continue;
+            }
+            let Ok((models, _)) = tobj::load_obj(&path, &GPU_LOAD_OPTIONS) else {
+                continue;
+            };
+
+            for model in models {
+                for max_leaf_size in [1, 4] {
+                    let mesh = Mesh::new(model.clone(), None, &BvhArgs { max_leaf_size });
+                    if mesh.faces.is_empty() {
+                        continue;
+                    }
+                    meshes_checked += 1;
+
+                    for _ in 0..200 {
+                        let origin = random_point(&mut rng, &mesh.bounding_box);
+                        let target = random_point(&mut rng, &mesh.bounding_box);
+                        let ray = Ray::new(&origin, &(target - origin).normalize());
+
+                        let bvh_hit = mesh.bvh.intersect(&ray, &mesh.faces);
+                        let brute_force_hit = get_min_intersection(&ray, mesh.faces.iter());
+                        assert_eq!(
+                            mesh.bvh.occluded(&ray, &mesh.faces),
+                            brute_force_hit.is_some(),
+                            "{}: any-hit query disagrees with brute force",
+                            path.display()
+                        );
+
+                        match (bvh_hit, brute_force_hit) {
+                            (None, None) => {}
+                            (Some(bvh_hit), Some(brute_force_hit)) => assert!(
+                                (bvh_hit.depth() - brute_force_hit.depth()).abs() < 1e-9,
+                                "{}: bvh depth {} differs from brute force {}",
+                                path.display(),
+                                bvh_hit.depth(),
+                                brute_force_hit.depth()
+                            ),
+                            (bvh_hit, brute_force_hit) => panic!(
+                                "{}: bvh hit {:?} but brute force hit {:?}",
+                                path.display(),
+                                bvh_hit.map(|hit| hit.depth()),
+                                brute_force_hit.map(|hit| hit.depth())
+                            ),
+                        }
+                    }
+                }
+            }
         }
 
-        obj.update_bounding_box();
-        obj
+        assert!(meshes_checked > 0);
+    }
+
+    #[test]
+    fn generated_normals_smooth_only_within_a_group() {
+        let path = "models/smoothing-normal.obj";
+        let (models, _) = tobj::load_obj(path, &GPU_LOAD_OPTIONS).unwrap();
+        let groups = smoothing_groups::load(path).unwrap();
+        let [front, back] = [0, 1].map(|index| models[index].clone());
+        let bvh_args = BvhArgs::default();
+        let hit = |mesh: &Mesh, origin: Vec3, direction: Vec3| {
+            mesh.intersect(&Ray::new(&origin, &direction)).unwrap()
+        };
+
+        // Close to the edge the front quad shares with the bottom one, its normal leans
+        // toward that of the bottom, -y. Without groups it stays flat.
+        let smooth = Mesh::new(front.clone(), Some(&groups[..4]), &bvh_args);
+        let near_edge = hit(&smooth, Vec3::new(1.0, 0.01, 3.0), -Vec3::z());
+        assert_eq!(near_edge.geometric_normal(), &Vec3::z());
+        let normal = near_edge.shading_normal();
+        assert!(normal.y < -0.3 && normal.z > 0.3 && (normal.norm() - 1.0).abs() < 1e-12);
+        let flat = Mesh::new(front, None, &bvh_args);
+        let near_edge = hit(&flat, Vec3::new(1.0, 0.01, 3.0), -Vec3::z());
+        assert_eq!(near_edge.shading_normal(), &Vec3::z());
+
+        // The back quad has smoothing off.
+        let back = Mesh::new(back, Some(&groups[4..]), &bvh_args);
+        let intersection = hit(&back, Vec3::new(1.0, 0.01, -1.0), Vec3::z());
+        assert_eq!(
+            intersection.shading_normal(),
+            intersection.geometric_normal()
+        );
     }
 }
@@ -1,5 +1,8 @@
-mod bounding_box;
+pub mod bounding_box;
+pub mod bvh;
 pub mod face;
 pub mod intersection;
 pub mod mesh;
 pub mod ray;
+pub mod smoothing_groups;
+pub mod transform;
@@ -3,34 +3,76 @@ use std::default::Default;
 
 const ADJUST_VALUE: f64 = 0.0001;
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
+    t_min: f64,
+    t_max: f64,
+    /// When the ray is traced, in frames, which decides where moving objects are.
+    time: f64,
+}
+
+impl Default for Ray {
+    fn default() -> Self {
+        Self::new(&Vec3::default(), &Vec3::default())
+    }
 }
 
 impl Ray {
     pub fn new(origin: &Vec3, direction: &Vec3) -> Self {
         Self {
-            origin: origin.clone(),
-            direction: direction.clone(),
+            origin: *origin,
+            direction: *direction,
+            t_min: 0.0,
+            t_max: f64::INFINITY,
+            time: 0.0,
+        }
+    }
+
+    /// The same ray, only hitting what lies between `t_min` and `t_max` along it.
+    pub fn with_interval(mut self, t_min: f64, t_max: f64) -> Self {
+        self.t_min = t_min;
+        self.t_max = t_max;
+        self
+    }
+
+    pub fn with_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+
+    /// The same ray in the coordinates of a transformed object. Interval and time stay, so
+    /// `direction` keeps the scale of the transform instead of being normalized, and
+    /// distances along both rays match.
+    pub fn transformed(&self, origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction,
+            ..*self
         }
     }
 
     pub fn new_with_adjusted_origin(origin: &Vec3, direction: &Vec3, normal: &Vec3) -> Self {
-        let mut ray = Self {
-            origin: *origin,
-            direction: *direction,
-        };
+        let mut ray = Self::new(origin, direction);
 
         ray.adjust_origin(normal);
         ray
     }
 
+    /// Shadow ray leaving the surface at `origin` that stops just short of `target`, so
+    /// whatever is sampled at `target` never occludes itself.
+    pub fn segment(origin: &Vec3, target: &Vec3, normal: &Vec3) -> Self {
+        let mut ray = Self::new(origin, &(target - origin).normalize());
+        ray.adjust_origin(normal);
+        let t_max = (target - ray.origin).norm() - ADJUST_VALUE;
+        ray.with_interval(0.0, t_max)
+    }
+
     pub fn adjust_origin(&mut self, normal: &Vec3) {
         let mut offset = ADJUST_VALUE * normal;
 
-        if self.direction.dot(&normal) < 0.0 {
+        if self.direction.dot(normal) < 0.0 {
             offset = 1.0 * offset;
         }
 
@@ -44,4 +86,66 @@ impl Ray {
     pub fn direction(&self) -> &Vec3 {
         &self.direction
     }
+
+    pub fn t_min(&self) -> f64 {
+        self.t_min
+    }
+
+    pub fn t_max(&self) -> f64 {
+        self.t_max
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Whether a hit at distance `t` lies inside the ray's `(t_min, t_max)` interval.
+    pub fn contains(&self, t: f64) -> bool {
+        t > self.t_min && t < self.t_max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object::{
+        bounding_box::BoundingBox, face::FaceBuilder, intersection::Intersectable,
+    };
+
+    use super::*;
+
+    #[test]
+    fn hits_outside_the_interval_are_ignored() {
+        let wall = FaceBuilder::new([
+            Vec3::new(-1.0, -1.0, 5.0),
+            Vec3::new(1.0, -1.0, 5.0),
+            Vec3::new(0.0, 1.0, 5.0),
+        ])
+        .build();
+        let origin = Vec3::zeros();
+
+        // A segment ends just short of a target on the wall and runs through one behind it.
+        let to_wall = Ray::segment(&origin, &Vec3::new(0.0, 0.0, 5.0), &Vec3::z());
+        assert!((to_wall.t_max() - (5.0 - 2.0 * ADJUST_VALUE)).abs() < 1e-12);
+        assert!(!wall.occluded(&to_wall));
+        assert!(wall.occluded(&Ray::segment(
+            &origin,
+            &Vec3::new(0.0, 0.0, 9.0),
+            &Vec3::z()
+        )));
+
+        let ray = Ray::new(&origin, &Vec3::z());
+        assert_eq!(wall.intersect(&ray).unwrap().depth(), 5.0);
+        assert!(wall
+            .intersect(&ray.with_interval(6.0, f64::INFINITY))
+            .is_none());
+        assert!(!wall.occluded(&ray.with_interval(0.0, 4.0)));
+
+        let bounds = BoundingBox::new(&Vec3::new(-1.0, -1.0, 2.0), &Vec3::new(1.0, 1.0, 8.0));
+        assert_eq!(bounds.hit_distance(&ray, f64::INFINITY), Some(2.0));
+        assert_eq!(
+            bounds.hit_distance(&ray.with_interval(3.0, 9.0), 9.0),
+            Some(3.0)
+        );
+        assert_eq!(bounds.hit_distance(&ray, 1.0), None);
+    }
 }
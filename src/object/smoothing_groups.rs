@@ -0,0 +1,90 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    iter,
+};
+
+/// Smoothing group of every triangle in an OBJ file, in the order tobj emits them when
+/// triangulating faces as fans. Group 0 means smoothing is off (`s off` or `s 0`), and
+/// so does a group that is not a number.
+pub fn load(obj_path: &str) -> std::io::Result<Vec<u32>> {
+    let reader = BufReader::new(File::open(obj_path)?);
+    let mut groups = Vec::new();
+    let mut current_group = 0;
+
+    for line in reader.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("s") => {
+                current_group = match words.next() {
+                    Some("off") | None => 0,
+                    Some(group) => group.parse().unwrap_or(0),
+                }
+            }
+            Some("f") => {
+                let vertex_count = words.count();
+                if vertex_count >= 3 {
+                    groups.extend(iter::repeat_n(current_group, vertex_count - 2));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use tobj::GPU_LOAD_OPTIONS;
+
+    use super::*;
+
+    #[test]
+    fn groups_follow_the_triangles_of_tobj() {
+        let path = "models/smoothing-normal.obj";
+        let (models, _) = tobj::load_obj(path, &GPU_LOAD_OPTIONS).unwrap();
+        let triangles: Vec<Vec<[f32; 3]>> = models
+            .iter()
+            .flat_map(|model| {
+                let positions = &model.mesh.positions;
+                model.mesh.indices.chunks_exact(3).map(|triangle| {
+                    triangle
+                        .iter()
+                        .map(|&index| {
+                            let index = index as usize * 3;
+                            [positions[index], positions[index + 1], positions[index + 2]]
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+
+        // The front and bottom quads are smoothed together, the back one is not.
+        assert_eq!(load(path).unwrap(), [1, 1, 1, 1, 0, 0]);
+        let all = |range: std::ops::Range<usize>, axis: usize, value: f32| {
+            triangles[range]
+                .iter()
+                .flatten()
+                .all(|position| position[axis] == value)
+        };
+        assert!(all(0..2, 2, 2.0) && all(2..4, 1, 0.0) && all(4..6, 2, 0.0));
+
+        for entry in std::fs::read_dir("models").unwrap() {
+            let path = entry.unwrap().path();
+            let Ok((models, _)) = tobj::load_obj(&path, &GPU_LOAD_OPTIONS) else {
+                continue;
+            };
+            let groups = load(path.to_str().unwrap()).unwrap();
+            let triangle_count: usize = models
+                .iter()
+                .map(|model| model.mesh.indices.len() / 3)
+                .sum();
+            // tobj keeps the indices of line and point elements too, in files without faces.
+            if !groups.is_empty() {
+                assert_eq!(groups.len(), triangle_count, "{}", path.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use nalgebra::Rotation3;
+use serde::Deserialize;
+
+use crate::{
+    helpers::{Mat3, Vec3},
+    keyframes::{self, Interpolation},
+};
+
+use super::{
+    intersection::{Intersectable, Intersection},
+    ray::Ray,
+};
+
+/// Placement of an object at one frame, relative to where the OBJ file puts it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransformKeyframe {
+    frame: f64,
+    #[serde(default)]
+    translation: Vec3,
+    /// Angles about the x, y and z axes in degrees, applied in that order.
+    #[serde(default)]
+    rotation: Vec3,
+    #[serde(default = "default_scale")]
+    scale: f64,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// Moves the OBJ object named `object` through `keyframes`, which must be in increasing
+/// frame order. It rotates and scales around `pivot`, in the coordinates of the OBJ file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransformArgs {
+    pub object: String,
+    #[serde(default)]
+    pivot: Vec3,
+    #[serde(default)]
+    interpolation: Interpolation,
+    keyframes: Vec<TransformKeyframe>,
+}
+
+impl TransformArgs {
+    /// Whether there are keyframes, in order, that do not scale the object to nothing.
+    pub fn is_valid(&self) -> bool {
+        !self.keyframes.is_empty()
+            && self
+                .keyframes
+                .windows(2)
+                .all(|pair| pair[0].frame < pair[1].frame)
+            && self.keyframes.iter().all(|keyframe| keyframe.scale > 0.0)
+    }
+
+    /// Transform at `time`, which may lie between frames.
+    pub fn at(&self, time: f64) -> Transform {
+        let track = |value: fn(&TransformKeyframe) -> Vec3| {
+            keyframes::interpolate(
+                self.interpolation,
+                &self.keyframes,
+                |keyframe| (keyframe.frame, value(keyframe)),
+                time,
+            )
+        };
+        let rotation = track(|keyframe| keyframe.rotation).map(f64::to_radians);
+        Transform {
+            pivot: self.pivot,
+            translation: track(|keyframe| keyframe.translation),
+            rotation: Rotation3::from_euler_angles(rotation.x, rotation.y, rotation.z).into_inner(),
+            scale: keyframes::interpolate(
+                self.interpolation,
+                &self.keyframes,
+                |keyframe| (keyframe.frame, keyframe.scale),
+                time,
+            ),
+        }
+    }
+}
+
+/// Rotation and uniform scale around a pivot followed by a translation, from the
+/// coordinates of an object to those of the scene.
+#[derive(Debug, Clone)]
+pub struct Transform {
+    pivot: Vec3,
+    translation: Vec3,
+    rotation: Mat3,
+    scale: f64,
+}
+
+impl Transform {
+    pub fn point(&self, point: &Vec3) -> Vec3 {
+        self.pivot + self.translation + self.rotation * (point - self.pivot) * self.scale
+    }
+
+    pub fn inverse_point(&self, point: &Vec3) -> Vec3 {
+        self.pivot
+            + self.rotation.transpose() * (point - self.pivot - self.translation) / self.scale
+    }
+
+    pub fn vector(&self, vector: &Vec3) -> Vec3 {
+        self.rotation * vector * self.scale
+    }
+
+    pub fn inverse_vector(&self, vector: &Vec3) -> Vec3 {
+        self.rotation.transpose() * vector / self.scale
+    }
+
+    /// Normals only rotate, as the scale is the same along every axis.
+    pub fn normal(&self, normal: &Vec3) -> Vec3 {
+        self.rotation * normal
+    }
+}
+
+/// Object that moves over time: rays are taken into its coordinates at their own time, so
+/// rays spread over the shutter interval blur it.
+pub struct Animated<T> {
+    object: T,
+    transform: TransformArgs,
+}
+
+impl<T> Animated<T> {
+    pub fn new(object: T, transform: TransformArgs) -> Self {
+        Self { object, transform }
+    }
+
+    fn local_ray(&self, ray: &Ray) -> (Transform, Ray) {
+        let transform = self.transform.at(ray.time());
+        let local = ray.transformed(
+            transform.inverse_point(ray.origin()),
+            transform.inverse_vector(ray.direction()),
+        );
+        (transform, local)
+    }
+}
+
+impl<T: Intersectable> Intersectable for Animated<T> {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (transform, local) = self.local_ray(ray);
+        let intersection = self.object.intersect(&local)?;
+        Some(intersection.transformed(&transform, -ray.direction()))
+    }
+
+    fn occluded(&self, ray: &Ray) -> bool {
+        let (_, local) = self.local_ray(ray);
+        self.object.occluded(&local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object::face::FaceBuilder;
+
+    use super::*;
+
+    #[test]
+    fn rays_hit_the_object_where_it_is_at_their_time() {
+        let transform: TransformArgs = serde_json::from_str(
+            r#"{
+                "object": "triangle",
+                "keyframes": [
+                    {"frame": 0},
+                    {"frame": 2, "translation": [0, 0, 4], "scale": 2},
+                    {"frame": 3, "translation": [0, 0, 4], "rotation": [0, 90, 0], "scale": 2}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(transform.is_valid());
+        let triangle = FaceBuilder::new([
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ])
+        .build();
+        let animated = Animated::new(triangle, transform);
+        let ray = |time| Ray::new(&Vec3::new(1.2, -1.2, -10.0), &Vec3::z()).with_time(time);
+
+        // Outside of the triangle at first, inside once it grew halfway to twice its size.
+        assert!(animated.intersect(&ray(0.0)).is_none());
+        let hit = animated.intersect(&ray(1.0)).unwrap();
+        assert!((hit.depth() - 12.0).abs() < 1e-9);
+        assert!((hit.point() - Vec3::new(1.2, -1.2, 2.0)).norm() < 1e-9);
+        assert!((hit.geometric_normal() - -Vec3::z()).norm() < 1e-9);
+        assert_eq!(hit.w_outgoing(), &-Vec3::z());
+        assert!(animated.occluded(&ray(2.0)));
+        // Turned edge-on to the ray, and staying so after the last keyframe.
+        assert!(!animated.occluded(&ray(3.0)));
+        assert!(!animated.occluded(&ray(7.0)));
+    }
+}
@@ -1,9 +1,27 @@
-use std::error::Error;
+use std::{
+    error::Error, fs::File, io::BufWriter, net::TcpListener, ops::Range, path::Path, time::Instant,
+};
 
 use anyhow::anyhow;
+use indicatif::{ProgressDrawTarget, ProgressStyle};
 use serde::Deserialize;
 
-use crate::{camera::CameraArgs, image::Image, light::LightArgs, renderer::Renderer, scene::Scene};
+use crate::{
+    camera::CameraArgs,
+    checkpoint::{fingerprint, Checkpoint, CheckpointArgs},
+    distributed::{self, Assignment, DistributedArgs},
+    film::{Film, DENOISER_FEATURES},
+    image::{denoise::DenoiserArgs, Image, OutputArgs},
+    light::LightArgs,
+    object::{bvh::BvhArgs, transform::TransformArgs},
+    renderer::{
+        tiles::TileArgs, AdaptiveSamplingArgs, CropArgs, ProgressiveArgs, RenderState, Renderer,
+    },
+    sampler::{PixelSampler, SamplerArgs},
+    scene::Scene,
+    shader::IntegratorArgs,
+    statistics::{Phases, Statistics},
+};
 
 #[derive(Debug, Deserialize)]
 pub struct Configuration {
@@ -11,16 +29,131 @@ pub struct Configuration {
     samples_per_pixel: usize,
     lights: Vec<LightArgs>,
     camera: CameraArgs,
+    /// Objects that move over the frames of an animation.
+    #[serde(default)]
+    transforms: Vec<TransformArgs>,
+    #[serde(default)]
+    bvh: BvhArgs,
+    #[serde(default)]
+    integrator: IntegratorArgs,
+    /// Seed of every random stream, so that renders of one configuration are identical.
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    sampler: SamplerArgs,
+    #[serde(default)]
+    tiles: TileArgs,
+    /// Renders only a window of the image when present.
+    #[serde(default)]
+    crop: Option<CropArgs>,
     #[serde(default = "default_output_file")]
     pub output_file: String,
+    #[serde(default)]
+    output: OutputArgs,
+    /// Samples each pixel only until its noise is low enough when present.
+    #[serde(default)]
+    adaptive_sampling: Option<AdaptiveSamplingArgs>,
+    /// Renders in passes and saves previews along the way when present.
+    #[serde(default)]
+    progressive: Option<ProgressiveArgs>,
+    /// Denoises the image before saving it when present.
+    #[serde(default)]
+    denoiser: Option<DenoiserArgs>,
+    /// Saves the accumulated samples so that the render can be resumed when present.
+    #[serde(default)]
+    checkpoint: Option<CheckpointArgs>,
+    /// How the `coordinate` command splits the render between workers.
+    #[serde(default)]
+    distributed: DistributedArgs,
+    /// The settings that change the image, which are hashed together with the files of
+    /// the scene once it is loaded.
+    #[serde(skip)]
+    scene_settings: Vec<u8>,
+}
+
+/// Settings that only change how long a render runs or how it is saved, so that a
+/// checkpoint can be resumed after they changed. The sample count stays part of the
+/// fingerprint for samplers that depend on it.
+const RESUMABLE_SETTINGS: [&str; 9] = [
+    "samples_per_pixel",
+    "tiles",
+    "output_file",
+    "output",
+    "adaptive_sampling",
+    "progressive",
+    "denoiser",
+    "checkpoint",
+    "distributed",
+];
+
+impl Configuration {
+    pub fn from_json(value: serde_json::Value) -> anyhow::Result<Self> {
+        let mut configuration: Self = serde_json::from_value(value.clone())?;
+
+        let mut scene_settings = value;
+        if let Some(settings) = scene_settings.as_object_mut() {
+            for key in RESUMABLE_SETTINGS {
+                if key != "samples_per_pixel" || !configuration.sampler.depends_on_sample_count() {
+                    settings.remove(key);
+                }
+            }
+        }
+        configuration.scene_settings = serde_json::to_vec(&scene_settings)?;
+
+        Ok(configuration)
+    }
 }
 
 fn default_output_file() -> String {
     "output.png".into()
 }
 
+/// `pattern` with a printf-style `%d` or `%04d` replaced by `frame`, or with `frame` added
+/// to the file stem in four digits when it has neither.
+fn frame_file(pattern: &str, frame: usize) -> String {
+    if let Some((before, after)) = pattern.split_once('%') {
+        if let Some((width, rest)) = after.split_once('d') {
+            if width.is_empty() || width.bytes().all(|byte| byte.is_ascii_digit()) {
+                let width = width.parse().unwrap_or(0);
+                return format!("{before}{frame:0width$}{rest}");
+            }
+        }
+    }
+    let path = Path::new(pattern);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}_{frame:04}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_{frame:04}"),
+    };
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Hash of `settings` and of every file the scene was loaded from, so that a checkpoint is
+/// not resumed after the model, its materials or its textures changed. Files that could
+/// not be read count by their name alone.
+fn scene_fingerprint(mut settings: Vec<u8>, scene: &Scene) -> u64 {
+    for path in scene.source_files() {
+        settings.extend(path.to_string_lossy().as_bytes());
+        if let Ok(contents) = std::fs::read(path) {
+            settings.extend(fingerprint(&contents).to_le_bytes());
+        }
+    }
+    fingerprint(&settings)
+}
+
 pub struct RayTracer {
     renderer: Renderer,
+    output: OutputArgs,
+    progressive: Option<ProgressiveArgs>,
+    denoiser: Option<DenoiserArgs>,
+    checkpoint: Option<CheckpointArgs>,
+    crop: Option<CropArgs>,
+    distributed: DistributedArgs,
+    /// Set for cameras that move over a sequence of frames.
+    animated_camera: Option<CameraArgs>,
+    fingerprint: u64,
 }
 
 impl RayTracer {
@@ -28,17 +161,121 @@ impl RayTracer {
         if !Image::valid_format(&configuration.output_file) {
             return Err(anyhow!("invalid extension of output file"));
         }
+        if !configuration.integrator.is_valid() {
+            return Err(anyhow!(
+                "continue_probability of the integrator must lie between 0 and 1"
+            ));
+        }
+        if configuration.crop.is_some()
+            && configuration
+                .camera
+                .exposure()
+                .is_some_and(|exposure| exposure.auto_exposure())
+        {
+            return Err(anyhow!(
+                "auto_exposure cannot be combined with a crop window"
+            ));
+        }
+        if !configuration.distributed.is_valid() {
+            return Err(anyhow!(
+                "assignment_timeout must be a positive number of seconds"
+            ));
+        }
+        if let Some(animation) = configuration.camera.animation() {
+            if !animation.is_valid() {
+                return Err(anyhow!(
+                    "camera keyframes are missing, out of order or leave no frames"
+                ));
+            }
+        }
+        if let Some(transform) = configuration
+            .transforms
+            .iter()
+            .find(|transform| !transform.is_valid())
+        {
+            return Err(anyhow!(
+                "keyframes of {} are missing, out of order or scale it to nothing",
+                transform.object
+            ));
+        }
+
+        let mut aovs = configuration.output.aovs().to_vec();
+        if configuration.denoiser.is_some() {
+            for feature in DENOISER_FEATURES {
+                if !aovs.contains(&feature) {
+                    aovs.push(feature);
+                }
+            }
+        }
 
         let lights = configuration
             .lights
             .into_iter()
             .map(|light| light.into())
             .collect();
-        Ok(RayTracer {
-            renderer: Renderer::new(
-                Scene::with_camera_args(&configuration.model_file, configuration.camera, lights),
+        let animated_camera = configuration
+            .camera
+            .animation()
+            .is_some()
+            .then(|| configuration.camera.clone());
+        let scene = Scene::with_camera_args(
+            &configuration.model_file,
+            configuration.camera,
+            lights,
+            &configuration.bvh,
+            &configuration.transforms,
+        )
+        .map_err(|error| anyhow!("{error}"))?;
+        let mut renderer = Renderer::new(
+            scene,
+            configuration.samples_per_pixel,
+            configuration.integrator.into(),
+            PixelSampler::new(
+                &configuration.sampler,
                 configuration.samples_per_pixel,
+                configuration.seed,
             ),
+        )
+        .with_aovs(aovs)
+        .with_tiles(configuration.tiles);
+        if let Some(crop) = &configuration.crop {
+            let window = crop.region();
+            if window.width == 0
+                || window.height == 0
+                || window.x + window.width > renderer.image_width()
+                || window.y + window.height > renderer.image_height()
+            {
+                return Err(anyhow!("crop window is empty or outside of the image"));
+            }
+            if let Some(base) = &crop.paste_into {
+                let (width, height) = Image::dimensions(base)?;
+                if (width, height) != (renderer.image_width(), renderer.image_height()) {
+                    return Err(anyhow!("{base} is not the size of the rendered image"));
+                }
+            }
+            renderer = renderer.with_crop_window(window);
+        }
+        if let Some(adaptive_sampling) = configuration.adaptive_sampling {
+            renderer = renderer.with_adaptive_sampling(adaptive_sampling);
+        }
+        let fingerprint = scene_fingerprint(configuration.scene_settings, renderer.scene());
+
+        Ok(RayTracer {
+            renderer,
+            output: configuration.output,
+            // Checkpoints are saved between passes, so checkpointed renders are progressive.
+            progressive: configuration.progressive.or_else(|| {
+                configuration
+                    .checkpoint
+                    .as_ref()
+                    .map(|_| ProgressiveArgs::default())
+            }),
+            denoiser: configuration.denoiser,
+            checkpoint: configuration.checkpoint,
+            crop: configuration.crop,
+            distributed: configuration.distributed,
+            animated_camera,
+            fingerprint,
         })
     }
 
@@ -48,12 +285,456 @@ impl RayTracer {
         samples_per_pixel: usize,
     ) -> Result<RayTracer, Box<dyn Error>> {
         Ok(RayTracer {
-            renderer: Renderer::new(Scene::new(obj_path, camera_path)?, samples_per_pixel),
+            renderer: Renderer::new(
+                Scene::new(obj_path, camera_path)?,
+                samples_per_pixel,
+                IntegratorArgs::default().into(),
+                PixelSampler::new(&SamplerArgs::default(), samples_per_pixel, 0),
+            ),
+            output: OutputArgs::default(),
+            progressive: None,
+            denoiser: None,
+            checkpoint: None,
+            crop: None,
+            distributed: DistributedArgs::default(),
+            animated_camera: None,
+            fingerprint: 0,
+        })
+    }
+
+    /// Problems with the scene that did not stop it from loading.
+    pub fn warnings(&self) -> &[String] {
+        self.renderer.scene().warnings()
+    }
+
+    /// Renders and saves the image, or every frame of an animated camera to the files
+    /// [`frame_file`] names after `output_file`; with `resume`, continues from the checkpoint
+    /// instead of starting over. The scene is only loaded once for all frames.
+    pub fn render(&mut self, output_file: &str, resume: bool) -> anyhow::Result<()> {
+        let Some(camera) = &self.animated_camera else {
+            return self.render_frame(output_file, resume, None);
+        };
+        if resume {
+            return Err(anyhow!("animations cannot be resumed"));
+        }
+        for frame in camera.animation().unwrap().frames() {
+            self.renderer
+                .scene_mut()
+                .set_camera(camera.camera_at(frame as f64));
+            self.render_frame(&frame_file(output_file, frame), false, Some(frame))?;
+        }
+        Ok(())
+    }
+
+    /// Renders one image, labeling the progress bar with `frame` for animations.
+    fn render_frame(
+        &self,
+        output_file: &str,
+        resume: bool,
+        frame: Option<usize>,
+    ) -> anyhow::Result<()> {
+        let rays_before = self.renderer.ray_counts();
+        let render_start = Instant::now();
+        let film = match &self.progressive {
+            Some(progressive) => {
+                let mut state = if resume {
+                    self.resume()?
+                } else {
+                    self.renderer.start()
+                };
+                self.start_progress_bar(state.samples, frame);
+                let mut last_checkpoint = Instant::now();
+
+                let film = self
+                    .renderer
+                    .render_progressive(progressive, &mut state, |preview, state| {
+                        if let Some(preview) = preview {
+                            self.save(preview, output_file)?;
+                        }
+                        if self.checkpoint.as_ref().is_some_and(|checkpoint| {
+                            last_checkpoint.elapsed().as_secs_f64() >= checkpoint.interval
+                        }) {
+                            self.save_checkpoint(state)?;
+                            last_checkpoint = Instant::now();
+                        }
+                        Ok(())
+                    })
+                    .map_err(|error| anyhow!("{error}"))?;
+                self.save_checkpoint(&state)?;
+                film
+            }
+            None if resume => return Err(anyhow!("resuming requires a checkpoint section")),
+            None => {
+                self.start_progress_bar(0, frame);
+                self.renderer.render().map_err(|error| anyhow!("{error}"))?
+            }
+        };
+        self.renderer.progress_bar().finish_and_clear();
+
+        let scene = self.renderer.scene();
+        let phases = Phases {
+            load: scene.load_time().as_secs_f64(),
+            build: scene.build_time().as_secs_f64(),
+            render: render_start.elapsed().as_secs_f64(),
+            ..self.finish(film, output_file)?
+        };
+        let statistics = Statistics::new(self.renderer.ray_counts() - rays_before, phases);
+        println!("{statistics}");
+        if self.output.statistics {
+            let path = Path::new(output_file);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let file = File::create(path.with_file_name(format!("{stem}_statistics.json")))?;
+            serde_json::to_writer_pretty(BufWriter::new(file), &statistics)?;
+        }
+        Ok(())
+    }
+
+    /// Shows the progress of the samples still to be taken on standard error, after the
+    /// number of the frame if there is one.
+    fn start_progress_bar(&self, samples_taken: usize, frame: Option<usize>) {
+        let pixels = (self.renderer.width() * self.renderer.height()) as u64;
+        let progress_bar = self.renderer.progress_bar();
+        let template = match frame {
+            Some(frame) => {
+                progress_bar.set_message(format!("Frame {frame}"));
+                "{msg} {elapsed_precise} [{wide_bar}] {percent:>3}% ETA {eta_precise}"
+            }
+            None => "{elapsed_precise} [{wide_bar}] {percent:>3}% ETA {eta_precise}",
+        };
+        progress_bar.set_style(ProgressStyle::with_template(template).unwrap());
+        progress_bar.set_length(pixels * self.renderer.samples_per_pixel() as u64);
+        progress_bar.set_position(pixels * samples_taken as u64);
+        progress_bar.reset_eta();
+        progress_bar.reset_elapsed();
+        progress_bar.set_draw_target(ProgressDrawTarget::stderr());
+    }
+
+    /// Renders `samples` of the tiles with indices in `tiles`, all of them by default, and
+    /// saves their pixels to `path` along with the sample range and tiles, for
+    /// [`Self::merge`].
+    pub fn render_partial(
+        &self,
+        samples: Option<Range<usize>>,
+        tiles: Option<Range<usize>>,
+        path: &str,
+    ) -> anyhow::Result<()> {
+        let all_tiles = self.renderer.tiles();
+        let samples_per_pixel = self.renderer.samples_per_pixel();
+        let samples = samples.unwrap_or(0..samples_per_pixel);
+        if samples.is_empty() || samples.end > samples_per_pixel {
+            return Err(anyhow!(
+                "samples must be a non-empty range below {samples_per_pixel}"
+            ));
+        }
+        let tiles = tiles.unwrap_or(0..all_tiles.len());
+        let Some(tiles) = all_tiles.get(tiles) else {
+            return Err(anyhow!("the image only has {} tiles", all_tiles.len()));
+        };
+
+        let assignment = Assignment {
+            samples,
+            tiles: tiles.to_vec(),
+        };
+        let pixels = distributed::render_assignment(&self.renderer, &assignment);
+        distributed::save_partial(
+            path,
+            &self.renderer,
+            &self.checkpoint_header(),
+            &assignment,
+            &pixels,
+        )?;
+        Ok(())
+    }
+
+    /// Renders the assignments of the coordinator listening on `address` until the image is
+    /// complete.
+    pub fn work(&self, address: &str) -> anyhow::Result<()> {
+        distributed::work(&self.renderer, &self.checkpoint_header(), address)?;
+        Ok(())
+    }
+
+    /// Splits the render into assignments for the workers connecting to `address`, then
+    /// merges and saves their results.
+    pub fn coordinate(&self, address: &str, output_file: &str) -> anyhow::Result<()> {
+        let assignments = self
+            .distributed
+            .assignments(&self.renderer.tiles(), self.renderer.samples_per_pixel());
+        let listener = TcpListener::bind(address)?;
+        let pixels = distributed::coordinate(
+            &self.renderer,
+            &self.checkpoint_header(),
+            &listener,
+            &assignments,
+            self.distributed.assignment_timeout(),
+        )?;
+        self.finish(self.renderer.film(&pixels), output_file)?;
+        Ok(())
+    }
+
+    /// Adds up the samples of the partial renders saved by [`Self::render_partial`], in the
+    /// given order, and saves the image. The partials must render every sample of every
+    /// pixel exactly once.
+    pub fn merge(&self, partials: &[String], output_file: &str) -> anyhow::Result<()> {
+        let expected = self.checkpoint_header();
+        let mut assignments = Vec::new();
+        let mut tile_pixels = Vec::new();
+        for path in partials {
+            let (header, assignment, pixels) =
+                distributed::load_partial(path, &self.renderer.window())?;
+            check_header(path, &header, &expected)?;
+            assignments.push(assignment);
+            tile_pixels.push(pixels);
+        }
+        distributed::check_coverage(
+            &self.renderer.window(),
+            self.renderer.samples_per_pixel(),
+            &assignments,
+        )?;
+
+        let mut pixels = self.renderer.start().pixels;
+        for (assignment, tile_pixels) in assignments.iter().zip(&tile_pixels) {
+            distributed::merge_assignment(&self.renderer, &mut pixels, assignment, tile_pixels);
+        }
+        self.finish(self.renderer.film(&pixels), output_file)?;
+        Ok(())
+    }
+
+    /// Denoises and saves the film, returning how long both took.
+    fn finish(&self, mut film: Film, output_file: &str) -> anyhow::Result<Phases> {
+        let denoise_start = Instant::now();
+        if let Some(denoiser) = &self.denoiser {
+            film.denoise(denoiser);
+        }
+        let write_start = Instant::now();
+        self.save(&film, output_file)?;
+
+        Ok(Phases {
+            denoise: (write_start - denoise_start).as_secs_f64(),
+            write: write_start.elapsed().as_secs_f64(),
+            ..Default::default()
         })
     }
 
-    pub fn render(&self, output_file: &str) {
-        let image = self.renderer.render().unwrap();
-        image.save(output_file).unwrap();
+    fn save(&self, film: &Film, output_file: &str) -> std::io::Result<()> {
+        match &self.crop {
+            Some(CropArgs {
+                paste_into: Some(base),
+                ..
+            }) => {
+                let window = self.renderer.window();
+                film.save_pasted(output_file, &self.output, base, window.x, window.y)
+            }
+            _ => film.save(output_file, &self.output),
+        }
+    }
+
+    fn save_checkpoint(&self, state: &RenderState) -> std::io::Result<()> {
+        let Some(checkpoint) = &self.checkpoint else {
+            return Ok(());
+        };
+        self.checkpoint_header().save(&checkpoint.file, state)
+    }
+
+    fn resume(&self) -> anyhow::Result<RenderState> {
+        let path = &self
+            .checkpoint
+            .as_ref()
+            .ok_or_else(|| anyhow!("resuming requires a checkpoint section"))?
+            .file;
+        self.load_checkpoint(path)
+    }
+
+    /// Loads a checkpoint, refusing ones of another render.
+    fn load_checkpoint(&self, path: &str) -> anyhow::Result<RenderState> {
+        let (checkpoint, state) = Checkpoint::load(path)?;
+        check_header(path, &checkpoint, &self.checkpoint_header())?;
+        Ok(state)
+    }
+
+    fn checkpoint_header(&self) -> Checkpoint {
+        Checkpoint {
+            fingerprint: self.fingerprint,
+            width: self.renderer.width(),
+            height: self.renderer.height(),
+            aov_names: self
+                .renderer
+                .aovs()
+                .iter()
+                .map(|aov| aov.name().to_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Refuses the file at `path` unless its header is `expected`.
+fn check_header(path: &str, header: &Checkpoint, expected: &Checkpoint) -> anyhow::Result<()> {
+    if header.fingerprint != expected.fingerprint {
+        return Err(anyhow!(
+            "{path} was rendered from a different scene or configuration"
+        ));
+    }
+    if header != expected {
+        return Err(anyhow!(
+            "{path} holds a different image size or set of AOVs"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn configuration(settings: serde_json::Value) -> Configuration {
+        let mut configuration = json!({
+            "model_file": "models/cornell_box_VI.obj",
+            "samples_per_pixel": 4,
+            "lights": [],
+            "camera": {"position": [280, 275, -330], "look_at": [280, 265, 0], "up": [0, 1, 0],
+                       "width": 8, "height": 8},
+        });
+        configuration
+            .as_object_mut()
+            .unwrap()
+            .extend(settings.as_object().unwrap().clone());
+        Configuration::from_json(configuration).unwrap()
+    }
+
+    fn ray_tracer(settings: serde_json::Value) -> RayTracer {
+        RayTracer::with_configuration(configuration(settings)).unwrap()
+    }
+
+    #[test]
+    fn fingerprints_cover_materials_and_stratification() {
+        let fingerprint = |settings| ray_tracer(settings).fingerprint;
+        let base = fingerprint(json!({}));
+        assert_eq!(fingerprint(json!({"samples_per_pixel": 16})), base);
+
+        let stratified = json!({"sampler": {"type": "Stratified"}});
+        let mut more_samples = stratified.clone();
+        more_samples["samples_per_pixel"] = json!(16);
+        assert_ne!(fingerprint(more_samples), fingerprint(stratified));
+
+        let directory = std::env::temp_dir().join(format!("raytracer-{}-mtl", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for file_name in ["cornell_box_VI.obj", "cornell_box_VI.mtl"] {
+            std::fs::copy(
+                Path::new("models").join(file_name),
+                directory.join(file_name),
+            )
+            .unwrap();
+        }
+        let model_file = directory.join("cornell_box_VI.obj");
+        let copy = json!({"model_file": model_file});
+        let before = fingerprint(copy.clone());
+        let mtl = directory.join("cornell_box_VI.mtl");
+        let materials = std::fs::read_to_string(&mtl).unwrap();
+        std::fs::write(&mtl, materials.replacen("Kd", "Kd 0.5 0.5 0.5\n#", 1)).unwrap();
+        let after = fingerprint(copy);
+        std::fs::remove_dir_all(directory).unwrap();
+        assert_ne!(after, before);
+    }
+
+    #[test]
+    fn merges_need_every_sample_of_every_pixel_once() {
+        let directory =
+            std::env::temp_dir().join(format!("raytracer-{}-partials", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = |name: &str| directory.join(name).to_str().unwrap().to_owned();
+
+        // Workers with other tile settings place their pixels by the tiles they saved.
+        let scanline = ray_tracer(json!({"tiles": {"size": 4, "order": "Scanline"}}));
+        scanline
+            .render_partial(Some(0..2), None, &path("first.rtp"))
+            .unwrap();
+        scanline
+            .render_partial(Some(2..4), Some(0..2), &path("top.rtp"))
+            .unwrap();
+        scanline
+            .render_partial(Some(2..4), Some(2..4), &path("bottom.rtp"))
+            .unwrap();
+        assert!(scanline
+            .render_partial(Some(2..5), None, &path("beyond.rtp"))
+            .is_err());
+
+        let merger = ray_tracer(json!({}));
+        let output = path("merged.png");
+        let merge = |partials: &[&str]| {
+            let partials: Vec<_> = partials.iter().map(|name| path(name)).collect();
+            merger.merge(&partials, &output)
+        };
+        merge(&["first.rtp", "top.rtp", "bottom.rtp"]).unwrap();
+        assert!(merge(&["first.rtp", "top.rtp"]).is_err());
+        assert!(merge(&["first.rtp", "top.rtp", "bottom.rtp", "top.rtp"]).is_err());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn crop_windows_refuse_auto_exposure() {
+        let settings = |auto_exposure: bool| {
+            json!({
+                "camera": {"position": [280, 275, -330], "look_at": [280, 265, 0],
+                           "up": [0, 1, 0], "width": 8, "height": 8,
+                           "exposure": {"auto_exposure": auto_exposure}},
+                "crop": {"x": 2, "y": 2, "width": 4, "height": 4},
+            })
+        };
+        assert!(RayTracer::with_configuration(configuration(settings(true))).is_err());
+        ray_tracer(settings(false));
+    }
+
+    #[test]
+    fn transforms_of_unknown_objects_are_errors() {
+        let transforms = json!({"transforms": [
+            {"object": "does_not_exist", "keyframes": [{"frame": 0}]},
+        ]});
+        assert!(RayTracer::with_configuration(configuration(transforms)).is_err());
+    }
+
+    #[test]
+    fn frame_files_number_patterns_or_stems() {
+        assert_eq!(frame_file("frames/shot_%d.png", 7), "frames/shot_7.png");
+        assert_eq!(frame_file("shot_%04d.exr", 12), "shot_0012.exr");
+        assert_eq!(frame_file("frames/shot.png", 3), "frames/shot_0003.png");
+        assert_eq!(frame_file("shot", 12345), "shot_12345");
+        // Anything but digits between % and d is not a pattern.
+        assert_eq!(frame_file("100%_done.png", 1), "100%_done_0001.png");
+    }
+
+    #[test]
+    fn animations_render_every_frame_from_one_scene() {
+        let directory =
+            std::env::temp_dir().join(format!("raytracer-{}-frames", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = |name: &str| directory.join(name).to_str().unwrap().to_owned();
+
+        let camera = |position: [f64; 3]| {
+            json!({"position": position, "look_at": [280, 265, 0], "up": [0, 1, 0],
+                   "width": 8, "height": 8})
+        };
+        let mut animated = camera([280.0, 275.0, -330.0]);
+        animated["animation"] = json!({"keyframes": [
+            {"frame": 1, "position": [280, 275, -330], "look_at": [280, 265, 0]},
+            {"frame": 2, "position": [200, 275, -330], "look_at": [280, 265, 0]},
+        ]});
+        ray_tracer(json!({"camera": animated}))
+            .render(&path("frame_%02d.png"), false)
+            .unwrap();
+
+        // Each frame matches a still render from its own camera position.
+        for (frame, position) in [(1, [280.0, 275.0, -330.0]), (2, [200.0, 275.0, -330.0])] {
+            let still = path(&format!("still_{frame}.png"));
+            ray_tracer(json!({"camera": camera(position)}))
+                .render(&still, false)
+                .unwrap();
+            let frame = std::fs::read(path(&format!("frame_{frame:02}.png"))).unwrap();
+            assert_eq!(frame, std::fs::read(&still).unwrap());
+        }
+        assert!(!Path::new(&path("frame_03.png")).exists());
+
+        std::fs::remove_dir_all(directory).unwrap();
     }
 }
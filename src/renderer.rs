@@ -1,56 +1,620 @@
-use nalgebra::Vector2;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::{ops::Range, sync::Mutex, time::Instant};
+
+use indicatif::ProgressBar;
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::Deserialize;
 
 use crate::{
-    helpers::{Color, Vec3},
-    image::Image,
+    film::{Aov, Film, FilmPixel},
+    light::light_sampler::LightSampler,
+    sampler::{PixelSampler, Sampler},
     scene::Scene,
-    shader::{better_path_tracer_shader::PathTracer, BetterShader},
+    shader::{Integrator, Shader},
+    statistics::{self, RayCounts},
 };
 
+use self::tiles::{Region, TileArgs};
+
+pub mod tiles;
+
+/// Stops sampling a pixel once the standard error of its luminance drops below
+/// `threshold` times its mean luminance. `samples_per_pixel` becomes the maximum.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdaptiveSamplingArgs {
+    #[serde(default = "default_min_samples")]
+    min_samples: usize,
+    #[serde(default = "default_threshold")]
+    threshold: f64,
+}
+
+fn default_min_samples() -> usize {
+    16
+}
+
+fn default_threshold() -> f64 {
+    0.02
+}
+
+impl Default for AdaptiveSamplingArgs {
+    fn default() -> Self {
+        Self {
+            min_samples: default_min_samples(),
+            threshold: default_threshold(),
+        }
+    }
+}
+
+/// Luminance below which the error is compared against this floor instead, so that dark
+/// pixels do not always take the maximum sample count.
+const MIN_LUMINANCE: f64 = 0.01;
+
+impl AdaptiveSamplingArgs {
+    fn converged(&self, pixel: &FilmPixel) -> bool {
+        pixel.samples() >= self.min_samples
+            && pixel.variance().sqrt() <= self.threshold * pixel.mean_luminance().max(MIN_LUMINANCE)
+    }
+}
+
+/// Renders in passes of `samples_per_pass` samples per pixel, previewing the current
+/// image every `preview_interval` seconds or `preview_passes` passes, and stopping after
+/// `time_limit` seconds even if not every sample was taken.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProgressiveArgs {
+    #[serde(default = "default_samples_per_pass")]
+    samples_per_pass: usize,
+    #[serde(default)]
+    time_limit: Option<f64>,
+    #[serde(default)]
+    preview_interval: Option<f64>,
+    #[serde(default)]
+    preview_passes: Option<usize>,
+}
+
+fn default_samples_per_pass() -> usize {
+    1
+}
+
+impl Default for ProgressiveArgs {
+    fn default() -> Self {
+        Self {
+            samples_per_pass: default_samples_per_pass(),
+            time_limit: None,
+            preview_interval: None,
+            preview_passes: None,
+        }
+    }
+}
+
+/// Renders only the `width` by `height` pixels from `x`, `y` of the image. With
+/// `paste_into`, they are pasted into that image of the whole frame, which is then saved
+/// as the output; otherwise the output holds just the window.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CropArgs {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    #[serde(default)]
+    pub paste_into: Option<String>,
+}
+
+impl CropArgs {
+    pub fn region(&self) -> Region {
+        Region {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// Samples accumulated so far: every pixel has received the samples with indices below
+/// `samples`, except where adaptive sampling stopped earlier.
+#[derive(Debug, PartialEq)]
+pub struct RenderState {
+    pub samples: usize,
+    pub pixels: Vec<FilmPixel>,
+}
+
 pub struct Renderer {
     scene: Scene,
     samples_per_pixel: usize,
+    integrator: Integrator,
+    /// Every pixel starts from a copy of this sampler, which is seeded per pixel sample
+    /// so that the image does not depend on how pixels are scheduled across threads.
+    sampler: PixelSampler,
+    aovs: Vec<Aov>,
+    adaptive_sampling: Option<AdaptiveSamplingArgs>,
+    tiles: TileArgs,
+    /// The pixels that are rendered, the whole image unless cropped.
+    window: Region,
+    /// Advanced by the samples of every pixel that is rendered, hidden until shown.
+    progress_bar: ProgressBar,
+    ray_counts: Mutex<RayCounts>,
 }
 
 impl Renderer {
-    pub fn new(scene: Scene, samples_per_pixel: usize) -> Self {
+    pub fn new(
+        scene: Scene,
+        samples_per_pixel: usize,
+        integrator: Integrator,
+        sampler: PixelSampler,
+    ) -> Self {
+        let window = Region {
+            x: 0,
+            y: 0,
+            width: scene.width(),
+            height: scene.height(),
+        };
         Self {
             scene,
             samples_per_pixel,
+            integrator,
+            sampler,
+            aovs: Vec::new(),
+            adaptive_sampling: None,
+            tiles: TileArgs::default(),
+            window,
+            progress_bar: ProgressBar::hidden(),
+            ray_counts: Mutex::default(),
+        }
+    }
+
+    pub fn with_aovs(mut self, aovs: Vec<Aov>) -> Self {
+        self.aovs = aovs;
+        self
+    }
+
+    pub fn with_adaptive_sampling(mut self, adaptive_sampling: AdaptiveSamplingArgs) -> Self {
+        self.adaptive_sampling = Some(adaptive_sampling);
+        self
+    }
+
+    pub fn with_tiles(mut self, tiles: TileArgs) -> Self {
+        self.tiles = tiles;
+        self
+    }
+
+    /// Renders only `window`, which must lie inside the image.
+    pub fn with_crop_window(mut self, window: Region) -> Self {
+        assert!(
+            window.x + window.width <= self.scene.width()
+                && window.y + window.height <= self.scene.height(),
+            "crop window outside of the image"
+        );
+        self.window = window;
+        self
+    }
+
+    /// Width of the rendered window.
+    pub fn width(&self) -> usize {
+        self.window.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.window.height
+    }
+
+    pub fn image_width(&self) -> usize {
+        self.scene.width()
+    }
+
+    pub fn image_height(&self) -> usize {
+        self.scene.height()
+    }
+
+    pub fn window(&self) -> Region {
+        self.window
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    pub fn progress_bar(&self) -> &ProgressBar {
+        &self.progress_bar
+    }
+
+    /// Rays traced by every render of this renderer so far.
+    pub fn ray_counts(&self) -> RayCounts {
+        *self.ray_counts.lock().unwrap()
+    }
+
+    pub fn samples_per_pixel(&self) -> usize {
+        self.samples_per_pixel
+    }
+
+    pub fn aovs(&self) -> &[Aov] {
+        &self.aovs
+    }
+
+    pub fn render(&self) -> Result<Film, Box<dyn std::error::Error>> {
+        let mut pixels = self.empty_pixels();
+        self.render_pass(&mut pixels, 0..self.samples_per_pixel);
+        Ok(self.film(&pixels))
+    }
+
+    /// State of a render that has not taken any sample yet.
+    pub fn start(&self) -> RenderState {
+        RenderState {
+            samples: 0,
+            pixels: self.empty_pixels(),
+        }
+    }
+
+    /// Renders like [`Self::render`], one pass at a time, continuing from `state`. Since
+    /// every sample is seeded by its index, the passes add up to the same image as a
+    /// single pass. `after_pass` is called after every pass but the last, with the
+    /// current image when a preview is due, and stops the render when it fails.
+    pub fn render_progressive(
+        &self,
+        progressive: &ProgressiveArgs,
+        state: &mut RenderState,
+        mut after_pass: impl FnMut(
+            Option<&Film>,
+            &RenderState,
+        ) -> Result<(), Box<dyn std::error::Error>>,
+    ) -> Result<Film, Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        let mut last_preview = start;
+        let mut passes = 0;
+
+        while state.samples < self.samples_per_pixel {
+            let pass_end =
+                (state.samples + progressive.samples_per_pass.max(1)).min(self.samples_per_pixel);
+            self.render_pass(&mut state.pixels, state.samples..pass_end);
+            state.samples = pass_end;
+            passes += 1;
+
+            if progressive
+                .time_limit
+                .is_some_and(|time_limit| start.elapsed().as_secs_f64() >= time_limit)
+            {
+                break;
+            }
+
+            let preview_due = progressive
+                .preview_passes
+                .is_some_and(|preview_passes| passes % preview_passes.max(1) == 0)
+                || progressive
+                    .preview_interval
+                    .is_some_and(|preview_interval| {
+                        last_preview.elapsed().as_secs_f64() >= preview_interval
+                    });
+            if state.samples == self.samples_per_pixel {
+                break;
+            }
+            if preview_due {
+                after_pass(Some(&self.film(&state.pixels)), state)?;
+                last_preview = Instant::now();
+            } else {
+                after_pass(None, state)?;
+            }
+        }
+
+        Ok(self.film(&state.pixels))
+    }
+
+    fn empty_pixels(&self) -> Vec<FilmPixel> {
+        vec![FilmPixel::new(&self.aovs); self.window.width * self.window.height]
+    }
+
+    /// Tiles of the window, in render order.
+    pub fn tiles(&self) -> Vec<Region> {
+        self.tiles.tiles(&self.window)
+    }
+
+    /// Resolves accumulated pixels, as kept in a [`RenderState`], into images exposed like
+    /// the camera does.
+    pub fn film(&self, pixels: &[FilmPixel]) -> Film {
+        let mut film = Film::new(self.window.width, self.window.height, &self.aovs, pixels);
+        let camera = self.scene.camera();
+        if let Some(exposure) = camera.exposure() {
+            let vignetting = exposure.vignetting.then(|| {
+                self.window
+                    .pixels()
+                    .map(|(x, y)| camera.vignetting(x, y))
+                    .collect()
+            });
+            film.expose(&exposure.gains(&film.beauty, vignetting));
         }
+        film
+    }
+
+    /// Adds the samples with indices in `samples` to every pixel.
+    fn render_pass(&self, pixels: &mut [FilmPixel], samples: Range<usize>) {
+        self.render_tiles(pixels, &self.tiles(), samples);
     }
 
-    pub fn render(&self) -> Result<Image, Box<dyn std::error::Error>> {
-        let width = self.scene.width();
-        let height = self.scene.height();
-        let shader = PathTracer::new(Vec3::new(0.05, 0.05, 0.55));
+    /// Adds the samples with indices in `samples` to the pixels of `tiles`. Threads split
+    /// the tiles into runs in render order, render a copy of their pixels and the copies
+    /// are written back at the end.
+    pub fn render_tiles(&self, pixels: &mut [FilmPixel], tiles: &[Region], samples: Range<usize>) {
         let light_sampler = self.scene.create_light_sampler();
 
-        let pixels_color: Vec<Color> = (0..height)
-            .into_par_iter()
-            .flat_map(|y| (0..width).into_par_iter().map(move |x| (y, x)))
-            .map(|(y, x)| {
-                let color =
-                    (0..self.samples_per_pixel)
-                        .into_iter()
-                        .fold(Color::default(), |a, _| {
-                            let mut rng = fastrand::Rng::new();
-                            let jitter = Vector2::new(rng.f64(), rng.f64());
-                            let intersection = self.scene.cast_ray(x, y, &jitter, &light_sampler);
-                            let color = shader.shade(
-                                &intersection,
-                                &self.scene,
-                                None,
-                                &light_sampler,
-                                &mut rng,
-                            );
-                            a + color
-                        });
-                color / self.samples_per_pixel as f64
+        let shared_pixels = &*pixels;
+        let rendered: Vec<(Region, Vec<FilmPixel>, RayCounts)> = tiles
+            .par_iter()
+            .map(|tile| {
+                // Every tile is rendered on a single thread, so the difference of the
+                // counters of the thread is what the tile traced.
+                let counts = statistics::counts();
+                let tile_pixels = tile
+                    .pixels()
+                    .map(|(x, y)| {
+                        let mut pixel = shared_pixels[self.window.index((x, y))].clone();
+                        self.render_pixel(&mut pixel, x, y, samples.clone(), &light_sampler);
+                        pixel
+                    })
+                    .collect();
+                self.progress_bar
+                    .inc((tile.width * tile.height * samples.len()) as u64);
+                (*tile, tile_pixels, statistics::counts() - counts)
             })
             .collect();
 
-        Ok(Image::new(width, height, pixels_color))
+        let mut ray_counts = self.ray_counts.lock().unwrap();
+        for (tile, tile_pixels, counts) in rendered {
+            *ray_counts += counts;
+            for (position, pixel) in tile.pixels().zip(tile_pixels) {
+                pixels[self.window.index(position)] = pixel;
+            }
+        }
+    }
+
+    fn render_pixel(
+        &self,
+        pixel: &mut FilmPixel,
+        x: usize,
+        y: usize,
+        samples: Range<usize>,
+        light_sampler: &impl LightSampler,
+    ) {
+        let mut sampler = self.sampler.clone();
+        for sample in samples {
+            if let Some(adaptive_sampling) = &self.adaptive_sampling {
+                if adaptive_sampling.converged(pixel) {
+                    break;
+                }
+            }
+            sampler.start_pixel_sample(x, y, sample);
+            let intersection = self.scene.cast_ray(x, y, &mut sampler);
+            let lighting = self.integrator.shade_lighting(
+                &intersection,
+                &self.scene,
+                None,
+                light_sampler,
+                &mut sampler,
+            );
+            pixel.add_sample(&self.aovs, &intersection, &lighting);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{
+        light::LightArgs, object::bvh::BvhArgs, sampler::SamplerArgs, shader::IntegratorArgs,
+    };
+
+    use crate::checkpoint::Checkpoint;
+
+    use super::*;
+
+    pub(crate) fn cornell_box_renderer(seed: u64, samples_per_pixel: usize) -> Renderer {
+        cornell_box_renderer_with(seed, samples_per_pixel, IntegratorArgs::default())
+    }
+
+    /// [`cornell_box_renderer`] shaded by `integrator`.
+    pub(crate) fn cornell_box_renderer_with(
+        seed: u64,
+        samples_per_pixel: usize,
+        integrator: IntegratorArgs,
+    ) -> Renderer {
+        let camera = serde_json::from_str(
+            r#"{"position": [280, 275, -330], "look_at": [280, 265, 0], "up": [0, 1, 0],
+                "angle_x": 90, "angle_y": 90, "width": 16, "height": 16}"#,
+        )
+        .unwrap();
+        let light: LightArgs = serde_json::from_str(
+            r#"{"type": "Area", "power": [0.3, 0.3, 0.3], "normal": [0, -1, 0],
+                "vertex": [[248, 548, 182], [328, 548, 262], [248, 548, 262]]}"#,
+        )
+        .unwrap();
+        let scene = Scene::with_camera_args(
+            "models/cornell_box_VI.obj",
+            camera,
+            vec![light.into()],
+            &BvhArgs::default(),
+            &[],
+        )
+        .unwrap();
+
+        Renderer::new(
+            scene,
+            samples_per_pixel,
+            integrator.into(),
+            PixelSampler::new(&SamplerArgs::default(), samples_per_pixel, seed),
+        )
+    }
+
+    fn render_with_threads(renderer: &Renderer, threads: usize) -> Film {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| renderer.render().unwrap())
+    }
+
+    #[test]
+    fn render_is_independent_of_thread_count() {
+        let renderer = cornell_box_renderer(42, 4);
+
+        let single_threaded = render_with_threads(&renderer, 1);
+        assert_eq!(single_threaded, render_with_threads(&renderer, 4));
+        assert_ne!(
+            single_threaded,
+            render_with_threads(&cornell_box_renderer(7, 4), 4)
+        );
+    }
+
+    #[test]
+    fn ray_counts_are_independent_of_thread_count() {
+        let [single_threaded, multi_threaded] = [1, 4].map(|threads| {
+            let renderer = cornell_box_renderer(2, 3);
+            render_with_threads(&renderer, threads);
+            renderer.ray_counts()
+        });
+
+        assert_eq!(single_threaded, multi_threaded);
+        assert_eq!(single_threaded.primary, 16 * 16 * 3);
+        assert!(single_threaded.shadow > 0 && single_threaded.secondary > 0);
+        assert!(single_threaded.bvh_node_visits > single_threaded.total());
+    }
+
+    #[test]
+    fn aovs_decompose_the_beauty_image() {
+        let film = cornell_box_renderer(3, 4)
+            .with_aovs(vec![
+                Aov::Direct,
+                Aov::Indirect,
+                Aov::SampleCount,
+                Aov::ObjectId,
+            ])
+            .render()
+            .unwrap();
+        let [direct, indirect, sample_count, object_id] =
+            [0, 1, 2, 3].map(|index| film.aovs[index].1.pixels());
+
+        for (index, beauty) in film.beauty.pixels().iter().enumerate() {
+            assert!((direct[index] + indirect[index] - beauty).norm() < 1e-9);
+            assert_eq!(sample_count[index].x, 4.0);
+            assert!(object_id[index].x >= -1.0 && object_id[index].x.fract() == 0.0);
+        }
+        assert!(indirect.iter().any(|color| color.x > 0.0));
+    }
+
+    #[test]
+    fn cropped_tiles_match_the_full_image() {
+        let full = cornell_box_renderer(6, 2).render().unwrap();
+        let window = Region {
+            x: 3,
+            y: 5,
+            width: 9,
+            height: 7,
+        };
+
+        for order in ["Scanline", "Spiral", "Hilbert"] {
+            let tiles =
+                serde_json::from_str(&format!(r#"{{"size": 4, "order": "{order}"}}"#)).unwrap();
+            let cropped = cornell_box_renderer(6, 2)
+                .with_tiles(tiles)
+                .with_crop_window(window)
+                .render()
+                .unwrap();
+
+            let expected: Vec<_> = window
+                .pixels()
+                .map(|(x, y)| full.beauty.pixels()[y * 16 + x])
+                .collect();
+            assert_eq!(cropped.beauty.pixels(), expected, "{order}");
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_early_on_converged_pixels() {
+        let film = cornell_box_renderer(5, 64)
+            .with_adaptive_sampling(AdaptiveSamplingArgs {
+                min_samples: 4,
+                threshold: 0.05,
+            })
+            .with_aovs(vec![Aov::SampleCount, Aov::Depth])
+            .render()
+            .unwrap();
+        let [sample_count, depth] = [0, 1].map(|index| film.aovs[index].1.pixels());
+
+        for (samples, depth) in sample_count.iter().zip(depth) {
+            assert!((4.0..=64.0).contains(&samples.x));
+            // Pixels where every sample missed the scene have no variance at all.
+            if depth.x == 0.0 {
+                assert_eq!(samples.x, 4.0);
+            }
+        }
+        assert!(sample_count.iter().any(|samples| samples.x > 4.0));
+        assert!(sample_count
+            .iter()
+            .any(|samples| samples.x < 64.0 && samples.x > 4.0));
+    }
+
+    #[test]
+    fn progressive_passes_add_up_to_a_single_pass() {
+        let renderer = cornell_box_renderer(9, 6).with_aovs(vec![Aov::Albedo, Aov::Variance]);
+        let progressive = ProgressiveArgs {
+            samples_per_pass: 4,
+            preview_passes: Some(1),
+            ..Default::default()
+        };
+
+        let mut previews = Vec::new();
+        let film = renderer
+            .render_progressive(&progressive, &mut renderer.start(), |film, _| {
+                if let Some(film) = film {
+                    previews.push(film.beauty.pixels().to_vec());
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(film, renderer.render().unwrap());
+        // One preview after the first pass, none after the last.
+        assert_eq!(previews.len(), 1);
+        assert_ne!(previews[0], film.beauty.pixels());
+
+        // A failing callback stops the render after the pass it follows.
+        let mut state = renderer.start();
+        assert!(renderer
+            .render_progressive(&progressive, &mut state, |_, _| Err("disk full".into()))
+            .is_err());
+        assert_eq!(state.samples, 4);
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted_render() {
+        let path =
+            std::env::temp_dir().join(format!("raytracer-{}.checkpoint", std::process::id()));
+        let path = path.to_str().unwrap();
+        let aovs = vec![Aov::Normal, Aov::Variance];
+
+        let interrupted = cornell_box_renderer(4, 2).with_aovs(aovs.clone());
+        let mut state = interrupted.start();
+        interrupted
+            .render_progressive(&ProgressiveArgs::default(), &mut state, |_, _| Ok(()))
+            .unwrap();
+        let checkpoint = Checkpoint {
+            fingerprint: 17,
+            width: interrupted.width(),
+            height: interrupted.height(),
+            aov_names: aovs.iter().map(|aov| aov.name().to_owned()).collect(),
+        };
+        checkpoint.save(path, &state).unwrap();
+        let (loaded, mut loaded_state) = Checkpoint::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!((&loaded, &loaded_state), (&checkpoint, &state));
+
+        let resumed = cornell_box_renderer(4, 6).with_aovs(aovs);
+        let film = resumed
+            .render_progressive(
+                &ProgressiveArgs::default(),
+                &mut loaded_state,
+                |_, _| Ok(()),
+            )
+            .unwrap();
+        assert_eq!(film, resumed.render().unwrap());
     }
 }
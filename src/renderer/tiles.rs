@@ -0,0 +1,184 @@
+use serde::Deserialize;
+
+/// Rectangle of pixels, in image coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    /// Coordinates of every pixel, row by row.
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y..self.y + self.height)
+            .flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+    }
+
+    pub fn contains(&self, other: &Region) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.width <= self.x + self.width
+            && other.y + other.height <= self.y + self.height
+    }
+
+    /// Index of the pixel at `x`, `y` in a row by row buffer of this region.
+    pub fn index(&self, (x, y): (usize, usize)) -> usize {
+        (y - self.y) * self.width + (x - self.x)
+    }
+}
+
+/// Order in which tiles are handed to the render threads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum TileOrder {
+    /// Row by row from the top left.
+    Scanline,
+    /// Outwards from the center of the image, which is usually where the subject is.
+    #[default]
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles next to each other.
+    Hilbert,
+}
+
+/// Splits the image into square tiles of `size` pixels, so that every thread works on
+/// nearby pixels. Tiles on the right and bottom borders may be smaller.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TileArgs {
+    #[serde(default = "default_size")]
+    size: usize,
+    #[serde(default)]
+    order: TileOrder,
+}
+
+fn default_size() -> usize {
+    32
+}
+
+impl Default for TileArgs {
+    fn default() -> Self {
+        Self {
+            size: default_size(),
+            order: TileOrder::default(),
+        }
+    }
+}
+
+impl TileArgs {
+    /// Tiles covering `region`, in render order.
+    pub fn tiles(&self, region: &Region) -> Vec<Region> {
+        let size = self.size.max(1);
+        let columns = region.width.div_ceil(size);
+        let rows = region.height.div_ceil(size);
+
+        let grid = match self.order {
+            TileOrder::Scanline => (0..rows)
+                .flat_map(|row| (0..columns).map(move |column| (column, row)))
+                .collect(),
+            TileOrder::Spiral => spiral(columns, rows),
+            TileOrder::Hilbert => hilbert(columns, rows),
+        };
+
+        grid.into_iter()
+            .map(|(column, row)| {
+                let (x, y) = (column * size, row * size);
+                Region {
+                    x: region.x + x,
+                    y: region.y + y,
+                    width: size.min(region.width - x),
+                    height: size.min(region.height - y),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Walks a square spiral around the center cell, with legs of length 1, 1, 2, 2, 3, 3…,
+/// keeping the cells inside the grid.
+fn spiral(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    const DIRECTIONS: [(i64, i64); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+    let mut cells = Vec::with_capacity(columns * rows);
+    let (mut x, mut y) = (((columns as i64) - 1) / 2, ((rows as i64) - 1) / 2);
+    let mut leg = 0;
+    while cells.len() < columns * rows {
+        let (dx, dy) = DIRECTIONS[leg % 4];
+        for _ in 0..leg / 2 + 1 {
+            if (0..columns as i64).contains(&x) && (0..rows as i64).contains(&y) {
+                cells.push((x as usize, y as usize));
+            }
+            x += dx;
+            y += dy;
+        }
+        leg += 1;
+    }
+    cells
+}
+
+/// Cells of a Hilbert curve over the smallest power of two square holding the grid,
+/// keeping the cells inside the grid.
+fn hilbert(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let side = columns.max(rows).next_power_of_two();
+
+    (0..side * side)
+        .map(|distance| hilbert_cell(side, distance))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+/// Cell at `distance` along the Hilbert curve of a `side` by `side` grid.
+fn hilbert_cell(side: usize, mut distance: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut scale = 1;
+    while scale < side {
+        let rx = 1 & (distance / 2);
+        let ry = 1 & (distance ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = scale - 1 - x;
+                y = scale - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += scale * rx;
+        y += scale * ry;
+        distance /= 4;
+        scale *= 2;
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_order_covers_each_pixel_once() {
+        let region = Region {
+            x: 3,
+            y: 5,
+            width: 37,
+            height: 22,
+        };
+
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = TileArgs { size: 8, order }.tiles(&region);
+            assert_eq!(tiles.len(), 5 * 3, "{order:?}");
+
+            let mut pixels: Vec<_> = tiles.iter().flat_map(|tile| tile.pixels()).collect();
+            pixels.sort_unstable();
+            let mut expected: Vec<_> = region.pixels().collect();
+            expected.sort_unstable();
+            assert_eq!(pixels, expected, "{order:?}");
+        }
+
+        // The spiral starts in the middle.
+        let spiral = TileArgs::default().tiles(&Region {
+            x: 0,
+            y: 0,
+            width: 96,
+            height: 96,
+        });
+        assert_eq!((spiral[0].x, spiral[0].y), (32, 32));
+    }
+}
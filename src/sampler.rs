@@ -0,0 +1,255 @@
+use serde::Deserialize;
+
+use crate::helpers::Vec2;
+
+use self::{
+    halton::HaltonSampler, independent::IndependentSampler, sobol::SobolSampler,
+    stratified::StratifiedSampler,
+};
+
+pub mod halton;
+pub mod independent;
+pub mod sobol;
+pub mod stratified;
+
+/// Source of the random numbers of one pixel sample. Every call consumes the next
+/// dimension, so the camera, light sampling and BSDF sampling of a path always draw
+/// from the same dimensions of the underlying sequence.
+pub trait Sampler {
+    /// Restarts the sequence at sample `index` of pixel `(x, y)`.
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize);
+
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> Vec2;
+
+    /// Position inside the pixel; the first dimensions of every sample.
+    fn get_pixel_2d(&mut self) -> Vec2 {
+        self.get_2d()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type")]
+pub enum SamplerArgs {
+    #[default]
+    Independent,
+    Stratified {
+        #[serde(default = "default_jitter")]
+        jitter: bool,
+    },
+    Halton,
+    Sobol,
+}
+
+fn default_jitter() -> bool {
+    true
+}
+
+impl SamplerArgs {
+    /// Whether the samples change with the number of samples per pixel, so that a render
+    /// cannot be continued with another one.
+    pub fn depends_on_sample_count(&self) -> bool {
+        matches!(self, Self::Stratified { .. })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PixelSampler {
+    Independent(IndependentSampler),
+    Stratified(StratifiedSampler),
+    Halton(HaltonSampler),
+    Sobol(SobolSampler),
+}
+
+impl PixelSampler {
+    pub fn new(args: &SamplerArgs, samples_per_pixel: usize, seed: u64) -> Self {
+        match args {
+            SamplerArgs::Independent => Self::Independent(IndependentSampler::new(seed)),
+            SamplerArgs::Stratified { jitter } => {
+                Self::Stratified(StratifiedSampler::new(samples_per_pixel, *jitter, seed))
+            }
+            SamplerArgs::Halton => Self::Halton(HaltonSampler::new(seed)),
+            SamplerArgs::Sobol => Self::Sobol(SobolSampler::new(seed)),
+        }
+    }
+}
+
+impl Sampler for PixelSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        match self {
+            Self::Independent(sampler) => sampler.start_pixel_sample(x, y, index),
+            Self::Stratified(sampler) => sampler.start_pixel_sample(x, y, index),
+            Self::Halton(sampler) => sampler.start_pixel_sample(x, y, index),
+            Self::Sobol(sampler) => sampler.start_pixel_sample(x, y, index),
+        }
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        match self {
+            Self::Independent(sampler) => sampler.get_1d(),
+            Self::Stratified(sampler) => sampler.get_1d(),
+            Self::Halton(sampler) => sampler.get_1d(),
+            Self::Sobol(sampler) => sampler.get_1d(),
+        }
+    }
+
+    fn get_2d(&mut self) -> Vec2 {
+        match self {
+            Self::Independent(sampler) => sampler.get_2d(),
+            Self::Stratified(sampler) => sampler.get_2d(),
+            Self::Halton(sampler) => sampler.get_2d(),
+            Self::Sobol(sampler) => sampler.get_2d(),
+        }
+    }
+
+    fn get_pixel_2d(&mut self) -> Vec2 {
+        match self {
+            Self::Independent(sampler) => sampler.get_pixel_2d(),
+            Self::Stratified(sampler) => sampler.get_pixel_2d(),
+            Self::Halton(sampler) => sampler.get_pixel_2d(),
+            Self::Sobol(sampler) => sampler.get_pixel_2d(),
+        }
+    }
+}
+
+/// Combines `values` into one well-mixed 64 bit hash.
+pub fn hash(values: &[u64]) -> u64 {
+    let (first, rest) = values.split_first().expect("at least one value to hash");
+    rest.iter()
+        .fold(splitmix64(*first), |hash, value| splitmix64(hash ^ value))
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn mix_bits(mut value: u64) -> u64 {
+    value ^= value >> 31;
+    value = value.wrapping_mul(0x7fb5_d329_728e_a185);
+    value ^= value >> 27;
+    value = value.wrapping_mul(0x81da_def4_bc2d_d44d);
+    value ^ (value >> 33)
+}
+
+/// Uniform value in `[0, 1)` from the high bits of a hash.
+fn unit_float(bits: u64) -> f64 {
+    (bits >> 11) as f64 * f64::EPSILON / 2.0
+}
+
+/// Element `index` of a pseudo-random permutation of `0..length` selected by `seed`,
+/// computed without storing the permutation (Kensler, "Correlated Multi-Jittered
+/// Sampling").
+fn permutation_element(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+
+        if index < length {
+            return (index.wrapping_add(seed)) % length;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether every one of the `n` intervals `[i / n, (i + 1) / n)` holds exactly one of
+    /// the `n` values.
+    fn stratified(values: &[f64]) -> bool {
+        let mut strata = vec![0; values.len()];
+        for value in values {
+            assert!((0.0..1.0).contains(value), "{value} outside [0, 1)");
+            strata[(value * values.len() as f64) as usize] += 1;
+        }
+        strata.iter().all(|&count| count == 1)
+    }
+
+    /// First dimensions of every sample of one pixel: the pixel position and a 1D value.
+    fn pixel_dimensions(args: SamplerArgs) -> [Vec<f64>; 3] {
+        const SAMPLES: usize = 64;
+
+        let mut sampler = PixelSampler::new(&args, SAMPLES, 3);
+        let mut dimensions: [Vec<f64>; 3] = Default::default();
+        for index in 0..SAMPLES {
+            sampler.start_pixel_sample(5, 7, index);
+            let pixel = sampler.get_pixel_2d();
+            dimensions[0].push(pixel.x);
+            dimensions[1].push(pixel.y);
+            dimensions[2].push(sampler.get_1d());
+        }
+        dimensions
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_stratify_each_pixel() {
+        let [_, _, one_dimensional] = pixel_dimensions(SamplerArgs::Stratified { jitter: true });
+        assert!(stratified(&one_dimensional));
+
+        // Only the base 2 dimension stratifies a power of two number of samples.
+        let [halton_x, _, _] = pixel_dimensions(SamplerArgs::Halton);
+        assert!(stratified(&halton_x));
+
+        assert!(pixel_dimensions(SamplerArgs::Sobol)
+            .iter()
+            .all(|dimension| stratified(dimension)));
+    }
+
+    #[test]
+    fn first_samples_are_uniform_across_pixels() {
+        const PIXELS: usize = 4096;
+
+        for args in [
+            SamplerArgs::Independent,
+            SamplerArgs::Stratified { jitter: true },
+            SamplerArgs::Halton,
+            SamplerArgs::Sobol,
+        ] {
+            let mut sampler = PixelSampler::new(&args, 16, 11);
+            let mut below_tenth = [0; 8];
+            for pixel in 0..PIXELS {
+                sampler.start_pixel_sample(pixel % 64, pixel / 64, 0);
+                for count in below_tenth.iter_mut() {
+                    if sampler.get_1d() < 0.1 {
+                        *count += 1;
+                    }
+                }
+            }
+
+            for (dimension, count) in below_tenth.iter().enumerate() {
+                let fraction = *count as f64 / PIXELS as f64;
+                assert!(
+                    (fraction - 0.1).abs() < 0.02,
+                    "{args:?}: {fraction} of dimension {dimension} below 0.1"
+                );
+            }
+        }
+    }
+}
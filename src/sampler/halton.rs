@@ -0,0 +1,81 @@
+use crate::helpers::Vec2;
+
+use super::{hash, mix_bits, permutation_element, Sampler};
+
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+const RESOLUTION: f64 = 1.0 / (1u64 << 32) as f64;
+
+/// Halton sequence with the `n`-th prime as base of dimension `n`, Owen-scrambled with a
+/// different seed for every pixel and dimension. Dimensions past the prime table reuse
+/// the bases with independent scrambles.
+#[derive(Debug, Clone)]
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_hash: u64,
+    index: u64,
+    dimension: u64,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_hash: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel_hash = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let base = PRIMES[self.dimension as usize % PRIMES.len()];
+        let scramble = hash(&[self.pixel_hash, self.dimension]);
+        self.dimension += 1;
+
+        owen_scrambled_radical_inverse(base, self.index, scramble)
+    }
+
+    fn get_2d(&mut self) -> Vec2 {
+        Vec2::new(self.get_1d(), self.get_1d())
+    }
+}
+
+/// Mirrors the base-`base` digits of `index` around the radix point, permuting each digit
+/// with a permutation that depends on all the digits before it. Digits are generated down
+/// to a resolution of 2^-32, like the 32 bit Sobol points.
+fn owen_scrambled_radical_inverse(base: u64, mut index: u64, scramble: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut inverse_base_m = 1.0;
+    let mut reversed_digits = 0u64;
+    let mut digit_index = 0u64;
+
+    while inverse_base_m > RESOLUTION {
+        let next = index / base;
+        let digit = index - next * base;
+        // The prefix stays below `base * 2^32`, so the digit index in the high bits keeps
+        // prefixes of different lengths, such as `0` and `00`, apart.
+        let digit_hash = mix_bits(scramble ^ (digit_index << 48) ^ reversed_digits) as u32;
+        let digit = permutation_element(digit as u32, base as u32, digit_hash) as u64;
+
+        reversed_digits = reversed_digits * base + digit;
+        inverse_base_m *= inverse_base;
+        digit_index += 1;
+        index = next;
+    }
+
+    (inverse_base_m * reversed_digits as f64).min(ONE_MINUS_EPSILON)
+}
@@ -0,0 +1,35 @@
+use fastrand::Rng;
+
+use crate::helpers::Vec2;
+
+use super::{hash, Sampler};
+
+/// Uncorrelated uniform random numbers, one stream per pixel sample.
+#[derive(Debug, Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: Rng::with_seed(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.rng = Rng::with_seed(hash(&[self.seed, x as u64, y as u64, index as u64]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.f64()
+    }
+
+    fn get_2d(&mut self) -> Vec2 {
+        Vec2::new(self.rng.f64(), self.rng.f64())
+    }
+}
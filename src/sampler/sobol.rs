@@ -0,0 +1,94 @@
+use crate::helpers::Vec2;
+
+use super::{hash, mix_bits, Sampler};
+
+/// Owen-scrambled Sobol points (Burley, "Practical Hash-based Owen Scrambling"). Every
+/// 1D or 2D request is a fresh pair of the first two Sobol dimensions with its own index
+/// shuffle and scramble, which keeps each pair well stratified without direction numbers
+/// for high dimensions.
+#[derive(Debug, Clone)]
+pub struct SobolSampler {
+    seed: u64,
+    pixel_hash: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_hash: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Shuffled sample index and scramble seeds of the next dimension.
+    fn next_dimension(&mut self) -> (u32, u64) {
+        let dimension_hash = hash(&[self.pixel_hash, self.dimension]);
+        self.dimension += 1;
+
+        let index = nested_uniform_scramble(self.index, dimension_hash as u32);
+        (index, mix_bits(dimension_hash))
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel_hash = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (index, scramble) = self.next_dimension();
+        to_unit(nested_uniform_scramble(sobol_0(index), scramble as u32))
+    }
+
+    fn get_2d(&mut self) -> Vec2 {
+        let (index, scramble) = self.next_dimension();
+        Vec2::new(
+            to_unit(nested_uniform_scramble(sobol_0(index), scramble as u32)),
+            to_unit(nested_uniform_scramble(
+                sobol_1(index),
+                (scramble >> 32) as u32,
+            )),
+        )
+    }
+}
+
+/// First Sobol dimension: the van der Corput sequence.
+fn sobol_0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+/// Second Sobol dimension, from the primitive polynomial `x + 1`.
+fn sobol_1(index: u32) -> u32 {
+    let mut direction = 1u32 << 31;
+    let mut value = 0;
+    for bit in 0..32 {
+        if (index >> bit) & 1 == 1 {
+            value ^= direction;
+        }
+        direction ^= direction >> 1;
+    }
+    value
+}
+
+fn laine_karras_permutation(mut value: u32, seed: u32) -> u32 {
+    value = value.wrapping_add(seed);
+    value ^= value.wrapping_mul(0x6c50b47c);
+    value ^= value.wrapping_mul(0xb82f1e52);
+    value ^= value.wrapping_mul(0xc7afe638);
+    value ^= value.wrapping_mul(0x8d22f6e6);
+    value
+}
+
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    laine_karras_permutation(value.reverse_bits(), seed).reverse_bits()
+}
+
+fn to_unit(value: u32) -> f64 {
+    value as f64 / (1u64 << 32) as f64
+}
@@ -0,0 +1,87 @@
+use crate::helpers::Vec2;
+
+use super::{hash, permutation_element, unit_float, Sampler};
+
+/// Splits every dimension into one stratum per sample of the pixel and visits the strata
+/// in a different random order for each dimension. 2D samples use a grid of
+/// `ceil(sqrt(n))` columns.
+#[derive(Debug, Clone)]
+pub struct StratifiedSampler {
+    samples_per_pixel: usize,
+    jitter: bool,
+    seed: u64,
+    pixel: (usize, usize),
+    index: usize,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: usize, jitter: bool, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            jitter,
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// Hash of the current pixel and dimension, shared by all samples of the pixel.
+    fn dimension_hash(&self) -> u64 {
+        hash(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension,
+        ])
+    }
+
+    fn offset(&self, dimension_hash: u64, axis: u64) -> f64 {
+        if self.jitter {
+            unit_float(hash(&[dimension_hash, self.index as u64, axis]))
+        } else {
+            0.5
+        }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension_hash = self.dimension_hash();
+        self.dimension += 1;
+
+        let strata = self.samples_per_pixel as u32;
+        let stratum = permutation_element(
+            (self.index % self.samples_per_pixel) as u32,
+            strata,
+            dimension_hash as u32,
+        );
+        (stratum as f64 + self.offset(dimension_hash, 0)) / strata as f64
+    }
+
+    fn get_2d(&mut self) -> Vec2 {
+        let dimension_hash = self.dimension_hash();
+        self.dimension += 1;
+
+        let columns = (self.samples_per_pixel as f64).sqrt().ceil() as usize;
+        let rows = self.samples_per_pixel.div_ceil(columns);
+        let strata = columns * rows;
+        let stratum = permutation_element(
+            (self.index % strata) as u32,
+            strata as u32,
+            dimension_hash as u32,
+        ) as usize;
+
+        Vec2::new(
+            ((stratum % columns) as f64 + self.offset(dimension_hash, 0)) / columns as f64,
+            ((stratum / columns) as f64 + self.offset(dimension_hash, 1)) / rows as f64,
+        )
+    }
+}
@@ -1,37 +1,97 @@
-use std::error::Error;
+use std::{
+    error::Error,
+    iter,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use nalgebra::Vector2;
 use tobj::{Material, GPU_LOAD_OPTIONS};
 
 use crate::{
     camera::{Camera, CameraArgs},
-    light::{
-        light_sampler::{power_sampler::PowerLightSampler, LightSampler},
-        Light,
-    },
+    light::{area_light::AreaLight, light_sampler::power_sampler::PowerLightSampler, Light},
     object::{
+        bounding_box::BoundingBox,
+        bvh::{Bounded, Bvh, BvhArgs},
         intersection::{get_min_intersection, Intersectable, Intersection, MaterialInformation},
         mesh::Mesh,
         ray::Ray,
+        smoothing_groups,
+        transform::{Animated, TransformArgs},
     },
+    sampler::Sampler,
+    statistics,
+    texture::{MaterialTextures, TextureCache},
 };
 
+/// Leaf of the top-level hierarchy: whole meshes, which carry their own face BVH, and
+/// area lights, which are single triangles.
+#[allow(clippy::large_enum_variant)]
+enum Primitive {
+    Mesh(Mesh),
+    AreaLight(AreaLight),
+}
+
+impl Bounded for Primitive {
+    fn get_bounding_box(&self) -> &BoundingBox {
+        match self {
+            Self::Mesh(mesh) => mesh.get_bounding_box(),
+            Self::AreaLight(light) => light.get_bounding_box(),
+        }
+    }
+}
+
+impl Intersectable for Primitive {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        match self {
+            Self::Mesh(mesh) => mesh.intersect(ray),
+            Self::AreaLight(light) => light.intersect(ray),
+        }
+    }
+
+    fn occluded(&self, ray: &Ray) -> bool {
+        match self {
+            Self::Mesh(mesh) => mesh.occluded(ray),
+            Self::AreaLight(light) => light.occluded(ray),
+        }
+    }
+}
+
 pub struct Scene {
     materials: Vec<Material>,
-    objects: Vec<Mesh>,
+    textures: Vec<MaterialTextures>,
+    primitives: Vec<Primitive>,
+    bvh: Bvh,
+    /// Objects with keyframed transforms, which stay out of the hierarchy because their
+    /// bounds change over time.
+    moving: Vec<Animated<Mesh>>,
     lights: Vec<Light>,
     camera: Camera,
+    /// Time spent reading the model, materials and textures.
+    load_time: Duration,
+    /// Time spent building the meshes and their hierarchies.
+    build_time: Duration,
+    /// Problems with the model that did not stop it from loading, like missing textures.
+    warnings: Vec<String>,
+    /// The model and the MTL libraries and textures it uses.
+    source_files: Vec<PathBuf>,
 }
 
 impl Scene {
-    pub fn with_camera_args(obj_path: &str, camera_args: CameraArgs, lights: Vec<Light>) -> Self {
+    pub fn with_camera_args(
+        obj_path: &str,
+        camera_args: CameraArgs,
+        lights: Vec<Light>,
+        bvh_args: &BvhArgs,
+        transforms: &[TransformArgs],
+    ) -> Result<Self, Box<dyn Error>> {
         let camera = camera_args.into();
-        Self::load_obj(obj_path, camera, lights).expect("Error loading model config")
+        Self::load_obj(obj_path, camera, lights, bvh_args, transforms)
     }
 
     pub fn new(obj_path: &str, camera_path: &str) -> Result<Self, Box<dyn Error>> {
         let camera = Camera::load(camera_path)?;
-        Self::load_obj(obj_path, camera, Vec::default())
+        Self::load_obj(obj_path, camera, Vec::default(), &BvhArgs::default(), &[])
     }
 
     pub fn width(&self) -> usize {
@@ -42,74 +102,235 @@ impl Scene {
         self.camera.height()
     }
 
+    /// Replaces the camera, which must keep the image size, without reloading the model.
+    pub fn set_camera(&mut self, camera: Camera) {
+        assert_eq!(
+            (camera.width(), camera.height()),
+            (self.width(), self.height()),
+            "camera changes the image size"
+        );
+        self.camera = camera;
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
     pub fn lights(&self) -> &[Light] {
         &self.lights
     }
 
-    pub fn visibility(&self, ray: &Ray, max_l: f64) -> bool {
-        !self.objects.iter().any(|object| {
-            object
-                .intersect(ray)
-                .map_or(false, |intersection| intersection.depth() < max_l)
-        })
+    pub fn load_time(&self) -> Duration {
+        self.load_time
+    }
+
+    pub fn build_time(&self) -> Duration {
+        self.build_time
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn source_files(&self) -> &[PathBuf] {
+        &self.source_files
+    }
+
+    /// Whether a shadow ray is blocked.
+    pub fn occluded(&self, ray: &Ray) -> bool {
+        statistics::count(|counts| counts.shadow += 1);
+        self.bvh.occluded(ray, &self.primitives)
+            || self.moving.iter().any(|object| object.occluded(ray))
     }
 
     fn load_obj(
         obj_path: &str,
         camera: Camera,
         lights: Vec<Light>,
+        bvh_args: &BvhArgs,
+        transforms: &[TransformArgs],
     ) -> Result<Self, Box<dyn Error>> {
+        let load_start = Instant::now();
         let (models, materials) = tobj::load_obj(obj_path, &GPU_LOAD_OPTIONS)?;
+        let smoothing_groups = smoothing_groups::load(obj_path).ok();
+        let materials = materials?;
+        let mut texture_cache = TextureCache::new(obj_path);
+        let textures = materials
+            .iter()
+            .map(|material| MaterialTextures::load(material, &mut texture_cache))
+            .collect();
+        let source_files = iter::once(PathBuf::from(obj_path))
+            .chain(texture_cache.files())
+            .collect();
+        let warnings = texture_cache.into_warnings();
+        let load_time = load_start.elapsed();
 
-        let objects = models.into_iter().map(Mesh::from).collect();
+        let build_start = Instant::now();
+        if let Some(missing) = transforms
+            .iter()
+            .find(|transform| models.iter().all(|model| model.name != transform.object))
+        {
+            return Err(format!("no object named {} to transform", missing.object).into());
+        }
+        let mut first_face = 0;
+        let mut moving = Vec::new();
+        let meshes: Vec<_> = models
+            .into_iter()
+            .enumerate()
+            .filter_map(|(object_id, model)| {
+                let face_count = model.mesh.indices.len() / 3;
+                let groups = smoothing_groups
+                    .as_ref()
+                    .and_then(|groups| groups.get(first_face..first_face + face_count));
+                first_face += face_count;
+                let transform = transforms
+                    .iter()
+                    .find(|transform| transform.object == model.name);
+                let mesh = Mesh::new(model, groups, bvh_args).with_object_id(object_id);
+                match transform {
+                    _ if mesh.is_empty() => None,
+                    Some(transform) => {
+                        moving.push(Animated::new(mesh, transform.clone()));
+                        None
+                    }
+                    None => Some(Primitive::Mesh(mesh)),
+                }
+            })
+            .collect();
+        let area_lights = lights.iter().filter_map(|light| match light {
+            Light::Area(light) => Some(Primitive::AreaLight(light.clone())),
+            _ => None,
+        });
+
+        let mut primitives = meshes.into_iter().chain(area_lights).collect();
+        let bvh = Bvh::new(&mut primitives, bvh_args);
+        let build_time = build_start.elapsed();
 
         Ok(Self {
             lights,
-            objects,
+            primitives,
+            bvh,
+            moving,
             camera,
-            materials: materials?,
+            materials,
+            textures,
+            load_time,
+            build_time,
+            warnings,
+            source_files,
         })
     }
 
-    pub fn create_light_sampler(&self) -> PowerLightSampler {
+    pub fn create_light_sampler(&self) -> PowerLightSampler<'_> {
         PowerLightSampler::new(self.lights.iter())
     }
 
-    pub fn trace<L: LightSampler>(&self, ray: &Ray, light_sampler: &L) -> Option<Intersection> {
-        let geometric_lights = light_sampler.geometric_lights();
-
-        let intersection = get_min_intersection(ray, self.objects.iter());
-
-        let light_intersection = get_min_intersection(ray, geometric_lights);
+    /// Closest hit of a ray after the first hit of a camera path.
+    pub fn trace(&self, ray: &Ray) -> Option<Intersection> {
+        statistics::count(|counts| counts.secondary += 1);
+        self.closest_hit(ray)
+    }
 
-        let mut min_intersection = [intersection, light_intersection]
+    fn closest_hit(&self, ray: &Ray) -> Option<Intersection> {
+        let still = self.bvh.intersect(ray, &self.primitives);
+        let moving = get_min_intersection(ray, self.moving.iter());
+        let mut min_intersection = still
             .into_iter()
-            .flatten()
-            .min_by(|a, b| a.depth().total_cmp(&b.depth()))?;
+            .chain(moving)
+            .min_by(|a, b| a.depth().total_cmp(&b.depth()))?
+            .with_time(ray.time());
 
         if !min_intersection.is_light() {
-            if let Some(MaterialInformation {
-                material_id,
-                material: _,
-            }) = &mut min_intersection.brdf
-            {
+            if let Some(&MaterialInformation { material_id, .. }) = min_intersection.brdf.as_ref() {
+                let textures = &self.textures[material_id];
+                textures.perturb_shading_normal(&mut min_intersection);
+
+                let mut material = self.materials[material_id].clone();
+                if let (Some(diffuse_map), Some(uv)) = (&textures.diffuse, min_intersection.uv()) {
+                    let texel = diffuse_map.lookup(uv);
+                    material.diffuse = Some([texel.x as f32, texel.y as f32, texel.z as f32]);
+                }
+
                 min_intersection.brdf = Some(MaterialInformation {
-                    material_id: *material_id,
-                    material: Some(self.materials[*material_id].clone()),
+                    material_id,
+                    material: Some(material),
                 });
             }
         }
         Some(min_intersection)
     }
 
-    pub fn cast_ray<L: LightSampler>(
-        &self,
-        x: usize,
-        y: usize,
-        jitter: &Vector2<f64>,
-        light_sampler: &L,
-    ) -> Option<Intersection> {
-        let ray = self.camera.get_ray(x, y, jitter);
-        self.trace(&ray, light_sampler)
+    pub fn cast_ray(&self, x: usize, y: usize, sampler: &mut impl Sampler) -> Option<Intersection> {
+        let ray = self.camera.get_ray(x, y, sampler)?;
+        statistics::count(|counts| counts.primary += 1);
+        self.closest_hit(&ray)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{helpers::Vec3, renderer::tests::cornell_box_renderer};
+
+    use super::*;
+
+    #[test]
+    fn bvh_matches_a_linear_scan_over_meshes_and_lights() {
+        let renderer = cornell_box_renderer(0, 1);
+        let scene = renderer.scene();
+        let bounds = scene
+            .primitives
+            .iter()
+            .fold(BoundingBox::empty(), |acc, primitive| {
+                acc.union(primitive.get_bounding_box())
+            });
+        let (min, max) = bounds.get_min_max();
+        let mut rng = fastrand::Rng::with_seed(11);
+        let mut random_point = || min + (max - min).map(|extent| extent * rng.f64());
+
+        for _ in 0..2000 {
+            let origin = random_point();
+            let ray = Ray::new(&origin, &(random_point() - origin).normalize());
+            let bvh_hit = scene.bvh.intersect(&ray, &scene.primitives);
+            let linear_hit = get_min_intersection(&ray, scene.primitives.iter());
+
+            assert_eq!(scene.occluded(&ray), linear_hit.is_some());
+            match (bvh_hit, linear_hit) {
+                (None, None) => {}
+                (Some(bvh_hit), Some(linear_hit)) => {
+                    assert!((bvh_hit.depth() - linear_hit.depth()).abs() < 1e-9);
+                    assert_eq!(bvh_hit.is_light(), linear_hit.is_light());
+                }
+                (bvh_hit, linear_hit) => panic!(
+                    "bvh hit {:?} but linear scan hit {:?}",
+                    bvh_hit.map(|hit| hit.depth()),
+                    linear_hit.map(|hit| hit.depth())
+                ),
+            }
+        }
+        // The area light is one of the primitives, straight up from the floor below it.
+        let up = Ray::new(&Vec3::new(288.0, 1.0, 242.0), &Vec3::y());
+        assert!(scene
+            .bvh
+            .intersect(&up, &scene.primitives)
+            .unwrap()
+            .is_light());
+    }
+
+    #[test]
+    fn shadow_rays_are_blocked_by_area_lights_but_not_by_their_target() {
+        let renderer = cornell_box_renderer(0, 1);
+        let scene = renderer.scene();
+        let floor = Vec3::new(288.0, 0.0, 242.0);
+        let segment = |target: Vec3| Ray::segment(&floor, &target, &Vec3::y());
+
+        // The light hangs just under the ceiling, so only the light lies between them.
+        assert!(!scene.occluded(&segment(Vec3::new(288.0, 548.0, 242.0))));
+        assert!(scene.occluded(&segment(Vec3::new(288.0, 548.4, 242.0))));
+        assert!(scene.occluded(&Ray::new(&floor, &Vec3::y())));
+        // Across the floor, straight through the short block.
+        let beside_block = Vec3::new(20.0, 1.0, 170.0);
+        let across = |x| Ray::segment(&beside_block, &Vec3::new(x, 100.0, 170.0), &Vec3::y());
+        assert!(scene.occluded(&across(400.0)));
+        assert!(!scene.occluded(&across(60.0)));
     }
 }
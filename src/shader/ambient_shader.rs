@@ -2,6 +2,7 @@ use crate::{
     helpers::{mul_vec3_with_rgb, Vec3, Zeroable},
     light::{light_sampler::LightSampler, Light},
     object::intersection::Intersection,
+    sampler::Sampler,
     scene::Scene,
 };
 
@@ -12,23 +13,24 @@ pub struct AmbientShader {
 }
 
 impl AmbientShader {
-    #[allow(dead_code)]
     pub fn new(background_color: Color) -> Self {
         Self { background_color }
     }
 }
 
 impl Shader for AmbientShader {
-    fn shade<L: LightSampler>(
+    fn shade<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Option<Intersection>,
         scene: &Scene,
         _depth: Option<u32>,
         _light_sampler: &L,
+        _sampler: &mut S,
     ) -> Color {
         let mut color = Vec3::new(0.0, 0.0, 0.0);
 
         match intersection {
+            Some(intersection) if intersection.is_light() => intersection.light_intensity.unwrap(),
             Some(intersection) => {
                 let material = intersection
                     .brdf
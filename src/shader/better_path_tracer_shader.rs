@@ -1,45 +1,45 @@
 use std::f64::consts::PI;
 
-use fastrand::Rng;
 use tobj::Material;
 
 use crate::{
     helpers::{Color, CoordinateSystemProvider, Rotateable, Vec3, Zeroable},
     light::{
         light_sample_context::LightSampleContext,
-        light_sampler::{self, LightSampler, SampleLight},
+        light_sampler::{LightSampler, SampleLight},
         Light, SampleLightResult,
     },
     object::{intersection::Intersection, ray::Ray},
+    sampler::Sampler,
     scene::Scene,
 };
 
-use super::BetterShader;
+use super::{Lighting, Shader};
 
 type Brdf = [f32; 3];
 
-const MAX_DEPTH: u32 = 2;
-
 pub struct PathTracer {
     background_color: Color,
+    max_depth: u32,
     continue_p: f64,
 }
 
 impl PathTracer {
-    pub fn new(background_color: Color) -> Self {
+    pub fn new(background_color: Color, max_depth: u32, continue_p: f64) -> Self {
         Self {
             background_color,
-            continue_p: 0.5,
+            max_depth,
+            continue_p,
         }
     }
 
-    pub fn direct_lighting<L: LightSampler>(
+    pub fn direct_lighting<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Intersection,
         material: &Material,
         scene: &Scene,
         light_sampler: &L,
-        rng: &mut Rng,
+        sampler: &mut S,
     ) -> Color {
         let mut color = Color::new(0.0, 0.0, 0.0);
 
@@ -54,7 +54,7 @@ impl PathTracer {
             light: light_sampled,
             power,
             sample_result,
-        }) = light_sampler.sample(LightSampleContext::new(intersection, &scene), rng)
+        }) = light_sampler.sample(LightSampleContext::new(intersection), sampler)
         {
             match light_sampled {
                 Light::Area(_) => {
@@ -64,19 +64,19 @@ impl PathTracer {
                                 color: light_color,
                                 pdf,
                                 cos,
-                                distance: light_distance,
-                                light_dir,
+                                point: light_point,
                                 ..
                             } = sample_result;
-                            let i_point = intersection.point();
-                            let light_dir = light_dir.unwrap();
-                            let light_distance = light_distance.unwrap();
+                            let light_point = light_point.unwrap();
                             let cos_l = cos.unwrap();
 
-                            let mut shadow = Ray::new(&i_point, &light_dir);
-                            shadow.adjust_origin(intersection.geometric_normal());
-
-                            if scene.visibility(&shadow, light_distance - 0.0001) {
+                            let shadow = Ray::segment(
+                                intersection.point(),
+                                &light_point,
+                                intersection.geometric_normal(),
+                            )
+                            .with_time(intersection.time());
+                            if !scene.occluded(&shadow) {
                                 let diffuse =
                                     [diffuse[0] as f64, diffuse[1] as f64, diffuse[2] as f64];
 
@@ -93,18 +93,20 @@ impl PathTracer {
                         if !diffuse.is_zero() {
                             let SampleLightResult {
                                 color: light_color,
-                                light_dir,
-                                distance: light_distance,
+                                point: light_point,
                                 cos,
                                 ..
                             } = sample_result;
-                            let light_dir = light_dir.unwrap();
-                            let light_distance = light_distance.unwrap();
+                            let light_point = light_point.unwrap();
                             let cos = cos.unwrap();
 
-                            let mut shadow = Ray::new(&intersection.point(), &light_dir);
-                            shadow.adjust_origin(intersection.geometric_normal());
-                            if scene.visibility(&shadow, light_distance) {
+                            let shadow = Ray::segment(
+                                intersection.point(),
+                                &light_point,
+                                intersection.geometric_normal(),
+                            )
+                            .with_time(intersection.time());
+                            if !scene.occluded(&shadow) {
                                 let diffuse =
                                     [diffuse[0] as f64, diffuse[1] as f64, diffuse[2] as f64];
                                 color +=
@@ -120,46 +122,48 @@ impl PathTracer {
         color
     }
 
-    fn specular_reflection<L: LightSampler>(
+    fn specular_reflection<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Intersection,
         material: &Brdf,
         scene: &Scene,
         depth: u32,
         light_sampler: &L,
-        rng: &mut Rng,
+        sampler: &mut S,
     ) -> Color {
         let gn = intersection.geometric_normal();
+        let sn = intersection.shading_normal();
         let wo = intersection.w_outgoing();
 
-        let cos = gn.dot(&wo);
+        let cos = sn.dot(wo);
 
-        let r_dir = 2.0 * cos * gn - wo;
-        let specular = Ray::new_with_adjusted_origin(&intersection.point(), &r_dir, &gn);
+        let r_dir = 2.0 * cos * sn - wo;
+        let specular = Ray::new_with_adjusted_origin(intersection.point(), &r_dir, gn)
+            .with_time(intersection.time());
 
-        let specular_intersection = scene.trace(&specular, light_sampler);
+        let specular_intersection = scene.trace(&specular);
         let r_color = self.shade(
             &specular_intersection,
             scene,
             Some(depth + 1),
             light_sampler,
-            rng,
+            sampler,
         );
 
         Vec3::from_column_slice(&[material[0] as f64, material[1] as f64, material[2] as f64])
             .component_mul(&r_color)
     }
 
-    fn diffuse_reflection<L: LightSampler>(
+    fn diffuse_reflection<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Intersection,
         material: &Brdf,
         scene: &Scene,
         depth: u32,
         light_sampler: &L,
-        rng: &mut Rng,
+        sampler: &mut S,
     ) -> Color {
-        let randoms = [rng.f64(), rng.f64()];
+        let randoms = sampler.get_2d();
 
         let sqrt_rand1 = randoms[1].sqrt();
         let d_around = Vec3::new(
@@ -172,14 +176,13 @@ impl PathTracer {
         let pdf = cos_theta / PI;
 
         let gn = intersection.geometric_normal();
-        let (rx, ry) = gn.coordinate_system();
+        let sn = intersection.shading_normal();
+        let (rx, ry) = sn.coordinate_system();
 
-        let diffuse = Ray::new_with_adjusted_origin(
-            intersection.point(),
-            &d_around.rotate(&rx, &ry, &gn),
-            gn,
-        );
-        let d_intersection = scene.trace(&diffuse, light_sampler);
+        let diffuse =
+            Ray::new_with_adjusted_origin(intersection.point(), &d_around.rotate(&rx, &ry, sn), gn)
+                .with_time(intersection.time());
+        let d_intersection = scene.trace(&diffuse);
         if let Some(d_intersection) = d_intersection {
             if !d_intersection.is_light() {
                 let r_color = self.shade(
@@ -187,7 +190,7 @@ impl PathTracer {
                     scene,
                     Some(depth + 1),
                     light_sampler,
-                    rng,
+                    sampler,
                 );
 
                 return (Color::from_column_slice(&[
@@ -203,32 +206,44 @@ impl PathTracer {
     }
 }
 
-impl BetterShader for PathTracer {
-    fn shade<L: LightSampler>(
+impl Shader for PathTracer {
+    fn shade<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Option<Intersection>,
         scene: &Scene,
         depth: Option<u32>,
         light_sampler: &L,
-        rng: &mut Rng,
+        sampler: &mut S,
     ) -> Color {
-        let mut color = Color::default();
+        self.shade_lighting(intersection, scene, depth, light_sampler, sampler)
+            .total()
+    }
+
+    fn shade_lighting<L: LightSampler, S: Sampler>(
+        &self,
+        intersection: &Option<Intersection>,
+        scene: &Scene,
+        depth: Option<u32>,
+        light_sampler: &L,
+        sampler: &mut S,
+    ) -> Lighting {
+        let mut lighting = Lighting::default();
         let Some(intersection) = intersection else {
-            return self.background_color;
+            return Lighting::direct(self.background_color);
         };
 
         let depth = depth.unwrap_or(0);
         if intersection.is_light() {
-            return intersection.light_intensity.unwrap();
+            return Lighting::direct(intersection.light_intensity.unwrap());
         }
 
         let Some(material) = intersection.brdf() else {
-            return color;
+            return lighting;
         };
 
-        let rnd_russian = rng.f64();
+        let rnd_russian = sampler.get_1d();
 
-        if depth < MAX_DEPTH || rnd_russian < self.continue_p {
+        if depth < self.max_depth || rnd_russian < self.continue_p {
             let specular_a = material.specular.unwrap_or([0., 0., 0.]);
             let diffuse_a = material.diffuse.unwrap_or([0., 0., 0.]);
             let specular = Color::from_column_slice(&[
@@ -247,7 +262,7 @@ impl BetterShader for PathTracer {
             } else {
                 0.0
             };
-            let rnd_spec = rng.f64();
+            let rnd_spec = sampler.get_1d();
 
             let l_color = if rnd_spec <= s_p || s_p >= (1. - f64::EPSILON) {
                 self.specular_reflection(
@@ -256,13 +271,19 @@ impl BetterShader for PathTracer {
                     scene,
                     depth,
                     light_sampler,
-                    rng,
+                    sampler,
                 ) / s_p
             } else {
-                self.diffuse_reflection(intersection, &diffuse_a, scene, depth, light_sampler, rng)
-                    / (1. - s_p)
+                self.diffuse_reflection(
+                    intersection,
+                    &diffuse_a,
+                    scene,
+                    depth,
+                    light_sampler,
+                    sampler,
+                ) / (1. - s_p)
             };
-            color += if depth < MAX_DEPTH {
+            lighting.indirect += if depth < self.max_depth {
                 l_color
             } else {
                 l_color / self.continue_p
@@ -271,10 +292,11 @@ impl BetterShader for PathTracer {
 
         if let Some(diffuse) = material.diffuse {
             if !diffuse.is_zero() {
-                color += self.direct_lighting(intersection, material, scene, light_sampler, rng);
+                lighting.direct +=
+                    self.direct_lighting(intersection, material, scene, light_sampler, sampler);
             }
         }
 
-        color
+        lighting
     }
 }
@@ -1,51 +1,63 @@
-use rand::Rng;
 use tobj::Material;
 
 use crate::{
-    helpers::{mul_vec3_with_rgb, Color, Vec2, Vec3, Zeroable},
+    helpers::{mul_vec3_with_rgb, Color, Vec3, Zeroable},
     light::{light_sampler::LightSampler, Light, SampleLightResult},
     object::{intersection::Intersection, ray::Ray},
+    sampler::Sampler,
     scene::Scene,
 };
 
-use super::Shader;
+use super::{Lighting, Shader};
 
 pub struct DistributedShader {
     background: Color,
+    max_depth: u32,
 }
 
 impl DistributedShader {
-    #[allow(dead_code)]
-    pub fn new(background: Color) -> Self {
-        Self { background }
+    pub fn new(background: Color, max_depth: u32) -> Self {
+        Self {
+            background,
+            max_depth,
+        }
     }
 
-    fn specular_reflection<L: LightSampler>(
+    fn specular_reflection<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Intersection,
         scene: &Scene,
         depth: u32,
         light_sampler: &L,
+        sampler: &mut S,
     ) -> Color {
         let wo = intersection.w_outgoing();
         let gn = intersection.geometric_normal();
+        let sn = intersection.shading_normal();
 
-        let cos = gn.dot(&wo);
+        let cos = sn.dot(wo);
 
-        let rdir = (2.0 * cos * gn) - wo;
-        let mut specular = Ray::new(&intersection.point(), &rdir);
+        let rdir = (2.0 * cos * sn) - wo;
+        let mut specular = Ray::new(intersection.point(), &rdir).with_time(intersection.time());
         specular.adjust_origin(gn);
 
-        let intersection = scene.trace(&specular, light_sampler);
+        let intersection = scene.trace(&specular);
 
-        self.shade(&intersection, scene, Some(depth + 1), light_sampler)
+        self.shade(
+            &intersection,
+            scene,
+            Some(depth + 1),
+            light_sampler,
+            sampler,
+        )
     }
 
-    fn direct_lighting(
+    fn direct_lighting<S: Sampler>(
         &self,
         intersection: &Intersection,
         brdf: &Material,
         scene: &Scene,
+        sampler: &mut S,
     ) -> Color {
         let mut color = Color::new(0.0, 0.0, 0.0);
 
@@ -69,16 +81,18 @@ impl DistributedShader {
                                 ..
                             } = point_light.l();
                             let light_pos = light_pos.unwrap();
-                            let mut light_dir = light_pos - intersection.point();
-                            let light_distance = light_dir.norm();
-                            light_dir.normalize_mut();
+                            let light_dir = (light_pos - intersection.point()).normalize();
 
-                            let cos = light_dir.dot(&intersection.shading_normal());
+                            let cos = light_dir.dot(intersection.shading_normal());
 
                             if cos > 0.0 {
-                                let mut shadow = Ray::new(&intersection.point(), &light_dir);
-                                shadow.adjust_origin(intersection.geometric_normal());
-                                if scene.visibility(&shadow, light_distance) {
+                                let shadow = Ray::segment(
+                                    intersection.point(),
+                                    &light_pos,
+                                    intersection.geometric_normal(),
+                                )
+                                .with_time(intersection.time());
+                                if !scene.occluded(&shadow) {
                                     let diffuse =
                                         [diffuse[0] as f64, diffuse[1] as f64, diffuse[2] as f64];
                                     color +=
@@ -91,8 +105,7 @@ impl DistributedShader {
                 Light::Area(area_light) => {
                     if let Some(diffuse) = brdf.diffuse {
                         if !diffuse.is_zero() {
-                            let mut rng = rand::thread_rng();
-                            let rnd = Vec2::new(rng.gen(), rng.gen());
+                            let rnd = sampler.get_2d();
 
                             let SampleLightResult {
                                 color: light_color,
@@ -102,18 +115,19 @@ impl DistributedShader {
                             } = area_light.l(&rnd);
                             let point = point.unwrap();
 
-                            let mut light_dir = point - intersection.point();
-                            let light_distance = light_dir.norm();
-                            light_dir.normalize_mut();
+                            let light_dir = (point - intersection.point()).normalize();
 
-                            let cos_l = light_dir.dot(&intersection.shading_normal());
-                            let cos_l_la = light_dir.dot(&area_light.normal());
+                            let cos_l = light_dir.dot(intersection.shading_normal());
+                            let cos_l_la = light_dir.dot(area_light.normal());
 
                             if cos_l > 0.0 && cos_l_la <= 0.0 {
-                                let mut shadow = Ray::new(&intersection.point(), &light_dir);
-                                shadow.adjust_origin(intersection.geometric_normal());
-
-                                if scene.visibility(&shadow, light_distance - 0.0001) {
+                                let shadow = Ray::segment(
+                                    intersection.point(),
+                                    &point,
+                                    intersection.geometric_normal(),
+                                )
+                                .with_time(intersection.time());
+                                if !scene.occluded(&shadow) {
                                     let diffuse =
                                         [diffuse[0] as f64, diffuse[1] as f64, diffuse[2] as f64];
 
@@ -134,21 +148,34 @@ impl DistributedShader {
 }
 
 impl Shader for DistributedShader {
-    fn shade<L: LightSampler>(
+    fn shade<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Option<Intersection>,
         scene: &Scene,
         depth: Option<u32>,
         light_sampler: &L,
+        sampler: &mut S,
     ) -> Color {
-        let mut color = Color::new(0.0, 0.0, 0.0);
+        self.shade_lighting(intersection, scene, depth, light_sampler, sampler)
+            .total()
+    }
+
+    fn shade_lighting<L: LightSampler, S: Sampler>(
+        &self,
+        intersection: &Option<Intersection>,
+        scene: &Scene,
+        depth: Option<u32>,
+        light_sampler: &L,
+        sampler: &mut S,
+    ) -> Lighting {
+        let mut lighting = Lighting::default();
 
         let Some(intersection) = intersection else {
-            return self.background;
+            return Lighting::direct(self.background);
         };
 
         if intersection.is_light() {
-            return intersection.light_intensity.unwrap();
+            return Lighting::direct(intersection.light_intensity.unwrap());
         }
 
         let material = intersection
@@ -161,17 +188,23 @@ impl Shader for DistributedShader {
 
         let depth = depth.unwrap_or(0);
         if let Some(specular) = material.specular {
-            if !specular.is_zero() && depth < 4 {
-                color += self.specular_reflection(intersection, scene, depth + 1, light_sampler);
+            if !specular.is_zero() && depth < self.max_depth {
+                lighting.indirect += self.specular_reflection(
+                    intersection,
+                    scene,
+                    depth + 1,
+                    light_sampler,
+                    sampler,
+                );
             }
         }
 
         if let Some(diffuse) = material.diffuse {
             if !diffuse.is_zero() {
-                color += self.direct_lighting(intersection, material, scene);
+                lighting.direct += self.direct_lighting(intersection, material, scene, sampler);
             }
         }
 
-        color
+        lighting
     }
 }
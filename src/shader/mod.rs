@@ -1,8 +1,14 @@
-use fastrand::Rng;
+use serde::Deserialize;
 
 use crate::{
     helpers::Color, light::light_sampler::LightSampler, object::intersection::Intersection,
-    scene::Scene,
+    sampler::Sampler, scene::Scene,
+};
+
+use self::{
+    ambient_shader::AmbientShader, better_path_tracer_shader::PathTracer,
+    distributed_shader::DistributedShader, path_tracer_shader::PathTracerShader,
+    whitted_shader::WhittedShader,
 };
 
 pub mod ambient_shader;
@@ -11,23 +17,276 @@ pub mod distributed_shader;
 pub mod path_tracer_shader;
 pub mod whitted_shader;
 
+/// Radiance of a path split at its first hit: light emitted there or reflected straight
+/// from a light source, and light that arrived after further bounces.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lighting {
+    pub direct: Color,
+    pub indirect: Color,
+}
+
+impl Lighting {
+    pub fn direct(color: Color) -> Self {
+        Self {
+            direct: color,
+            indirect: Color::zeros(),
+        }
+    }
+
+    pub fn total(&self) -> Color {
+        self.direct + self.indirect
+    }
+}
+
 pub trait Shader {
-    fn shade<L: LightSampler>(
+    fn shade<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Option<Intersection>,
         scene: &Scene,
         depth: Option<u32>,
         light_sampler: &L,
+        sampler: &mut S,
     ) -> Color;
+
+    /// [`Self::shade`] with the direct and indirect contributions kept apart. Shaders
+    /// without secondary bounces report everything as direct.
+    fn shade_lighting<L: LightSampler, S: Sampler>(
+        &self,
+        intersection: &Option<Intersection>,
+        scene: &Scene,
+        depth: Option<u32>,
+        light_sampler: &L,
+        sampler: &mut S,
+    ) -> Lighting {
+        Lighting::direct(self.shade(intersection, scene, depth, light_sampler, sampler))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum IntegratorType {
+    Ambient,
+    Whitted,
+    Distributed,
+    /// Path tracer that gathers direct light from every light at each bounce.
+    PathTracerAllLights,
+    /// Path tracer that samples a single light per bounce, proportionally to its power.
+    #[default]
+    PathTracer,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IntegratorArgs {
+    #[serde(rename = "type", default)]
+    integrator_type: IntegratorType,
+    #[serde(default = "default_background")]
+    background: Color,
+    /// Depth limit of reflections; for path tracers, the depth after which Russian
+    /// roulette starts. Defaults to the limit each integrator used to hard-code.
+    #[serde(default)]
+    max_depth: Option<u32>,
+    /// Chance that a path tracer keeps following a path at each bounce past `max_depth`.
+    #[serde(default = "default_continue_probability")]
+    continue_probability: f64,
+}
+
+fn default_background() -> Color {
+    Color::new(0.05, 0.05, 0.55)
+}
+
+fn default_continue_probability() -> f64 {
+    0.5
+}
+
+impl Default for IntegratorArgs {
+    fn default() -> Self {
+        Self {
+            integrator_type: IntegratorType::default(),
+            background: default_background(),
+            max_depth: None,
+            continue_probability: default_continue_probability(),
+        }
+    }
+}
+
+impl IntegratorArgs {
+    /// Whether paths past `max_depth` can both continue and end. Only the path tracers
+    /// read `continue_probability`.
+    pub fn is_valid(&self) -> bool {
+        match self.integrator_type {
+            IntegratorType::PathTracerAllLights | IntegratorType::PathTracer => {
+                self.continue_probability > 0.0 && self.continue_probability < 1.0
+            }
+            IntegratorType::Ambient | IntegratorType::Whitted | IntegratorType::Distributed => true,
+        }
+    }
+}
+
+pub enum Integrator {
+    Ambient(AmbientShader),
+    Whitted(WhittedShader),
+    Distributed(DistributedShader),
+    PathTracerAllLights(PathTracerShader),
+    PathTracer(PathTracer),
+}
+
+impl From<IntegratorArgs> for Integrator {
+    fn from(value: IntegratorArgs) -> Self {
+        let IntegratorArgs {
+            integrator_type,
+            background,
+            max_depth,
+            continue_probability,
+        } = value;
+
+        match integrator_type {
+            IntegratorType::Ambient => Self::Ambient(AmbientShader::new(background)),
+            IntegratorType::Whitted => {
+                Self::Whitted(WhittedShader::new(background, max_depth.unwrap_or(3)))
+            }
+            IntegratorType::Distributed => {
+                Self::Distributed(DistributedShader::new(background, max_depth.unwrap_or(4)))
+            }
+            IntegratorType::PathTracerAllLights => Self::PathTracerAllLights(
+                PathTracerShader::new(background, max_depth.unwrap_or(2), continue_probability),
+            ),
+            IntegratorType::PathTracer => Self::PathTracer(PathTracer::new(
+                background,
+                max_depth.unwrap_or(2),
+                continue_probability,
+            )),
+        }
+    }
 }
 
-pub trait BetterShader {
-    fn shade<L: LightSampler>(
+impl Shader for Integrator {
+    fn shade<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Option<Intersection>,
         scene: &Scene,
         depth: Option<u32>,
         light_sampler: &L,
-        rng: &mut Rng,
-    ) -> Color;
+        sampler: &mut S,
+    ) -> Color {
+        match self {
+            Self::Ambient(shader) => {
+                shader.shade(intersection, scene, depth, light_sampler, sampler)
+            }
+            Self::Whitted(shader) => {
+                shader.shade(intersection, scene, depth, light_sampler, sampler)
+            }
+            Self::Distributed(shader) => {
+                shader.shade(intersection, scene, depth, light_sampler, sampler)
+            }
+            Self::PathTracerAllLights(shader) => {
+                shader.shade(intersection, scene, depth, light_sampler, sampler)
+            }
+            Self::PathTracer(shader) => {
+                shader.shade(intersection, scene, depth, light_sampler, sampler)
+            }
+        }
+    }
+
+    fn shade_lighting<L: LightSampler, S: Sampler>(
+        &self,
+        intersection: &Option<Intersection>,
+        scene: &Scene,
+        depth: Option<u32>,
+        light_sampler: &L,
+        sampler: &mut S,
+    ) -> Lighting {
+        match self {
+            Self::Ambient(shader) => {
+                shader.shade_lighting(intersection, scene, depth, light_sampler, sampler)
+            }
+            Self::Whitted(shader) => {
+                shader.shade_lighting(intersection, scene, depth, light_sampler, sampler)
+            }
+            Self::Distributed(shader) => {
+                shader.shade_lighting(intersection, scene, depth, light_sampler, sampler)
+            }
+            Self::PathTracerAllLights(shader) => {
+                shader.shade_lighting(intersection, scene, depth, light_sampler, sampler)
+            }
+            Self::PathTracer(shader) => {
+                shader.shade_lighting(intersection, scene, depth, light_sampler, sampler)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{film::Film, renderer::tests::cornell_box_renderer_with};
+
+    use super::*;
+
+    fn args(json: &str) -> IntegratorArgs {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn render(args: IntegratorArgs) -> Film {
+        assert!(args.is_valid());
+        cornell_box_renderer_with(5, 2, args).render().unwrap()
+    }
+
+    #[test]
+    fn every_integrator_type_builds_its_integrator() {
+        let integrator = |integrator_type: &str| {
+            Integrator::from(args(&format!(r#"{{"type": "{integrator_type}"}}"#)))
+        };
+
+        assert!(matches!(integrator("Ambient"), Integrator::Ambient(_)));
+        assert!(matches!(integrator("Whitted"), Integrator::Whitted(_)));
+        assert!(matches!(
+            integrator("Distributed"),
+            Integrator::Distributed(_)
+        ));
+        assert!(matches!(
+            integrator("PathTracerAllLights"),
+            Integrator::PathTracerAllLights(_)
+        ));
+        assert!(matches!(
+            integrator("PathTracer"),
+            Integrator::PathTracer(_)
+        ));
+        assert!(matches!(
+            Integrator::from(IntegratorArgs::default()),
+            Integrator::PathTracer(_)
+        ));
+    }
+
+    #[test]
+    fn integrators_render_the_scene_their_own_way() {
+        let films: Vec<_> = [
+            "Ambient",
+            "Whitted",
+            "Distributed",
+            "PathTracerAllLights",
+            "PathTracer",
+        ]
+        .into_iter()
+        .map(|integrator_type| render(args(&format!(r#"{{"type": "{integrator_type}"}}"#))))
+        .collect();
+
+        for film in &films {
+            assert!(film.beauty.pixels().iter().all(|pixel| pixel
+                .iter()
+                .all(|channel| channel.is_finite() && *channel >= 0.0)));
+        }
+        // Neither ambient light nor mirror reflections alone look like a path traced image.
+        assert_ne!(films[0].beauty, films[4].beauty);
+        assert_ne!(films[1].beauty, films[4].beauty);
+
+        // Integrators without Russian roulette ignore the continue probability.
+        let whitted = args(r#"{"type": "Whitted", "continue_probability": 1.5}"#);
+        assert_eq!(render(whitted).beauty, films[1].beauty);
+
+        for continue_probability in [0.0, 1.0, 1.5] {
+            let args = IntegratorArgs {
+                continue_probability,
+                ..Default::default()
+            };
+            assert!(!args.is_valid());
+        }
+    }
 }
@@ -1,38 +1,40 @@
-use rand::Rng;
 use tobj::Material;
 
 use crate::{
-    helpers::{Color, CoordinateSystemProvider, Rotateable, Vec2, Vec3, Zeroable},
+    helpers::{Color, CoordinateSystemProvider, Rotateable, Vec3, Zeroable},
     light::{light_sampler::LightSampler, Light, SampleLightResult},
     object::{intersection::Intersection, ray::Ray},
+    sampler::Sampler,
     scene::Scene,
 };
 
 use std::f64::consts::PI;
 
-use super::Shader;
+use super::{Lighting, Shader};
 
 type Brdf = [f32; 3];
-const MAX_DEPTH: u32 = 2;
 
 pub struct PathTracerShader {
     background: Color,
+    max_depth: u32,
     continue_p: f64,
 }
 
 impl PathTracerShader {
-    pub fn new(background: Color) -> Self {
+    pub fn new(background: Color, max_depth: u32, continue_p: f64) -> Self {
         Self {
             background,
-            continue_p: 0.5,
+            max_depth,
+            continue_p,
         }
     }
 
-    fn direct_lighting(
+    fn direct_lighting<S: Sampler>(
         &self,
         intersection: &Intersection,
         brdf: &Material,
         scene: &Scene,
+        sampler: &mut S,
     ) -> Color {
         let mut color = Color::new(0.0, 0.0, 0.0);
 
@@ -55,16 +57,18 @@ impl PathTracerShader {
                                 ..
                             } = point_light.l();
                             let light_pos = light_pos.unwrap();
-                            let mut light_dir = light_pos - intersection.point();
-                            let light_distance = light_dir.norm();
-                            light_dir.normalize_mut();
+                            let light_dir = (light_pos - intersection.point()).normalize();
 
-                            let cos = light_dir.dot(&intersection.shading_normal());
+                            let cos = light_dir.dot(intersection.shading_normal());
 
                             if cos > 0.0 {
-                                let mut shadow = Ray::new(&intersection.point(), &light_dir);
-                                shadow.adjust_origin(intersection.geometric_normal());
-                                if scene.visibility(&shadow, light_distance) {
+                                let shadow = Ray::segment(
+                                    intersection.point(),
+                                    &light_pos,
+                                    intersection.geometric_normal(),
+                                )
+                                .with_time(intersection.time());
+                                if !scene.occluded(&shadow) {
                                     let diffuse =
                                         [diffuse[0] as f64, diffuse[1] as f64, diffuse[2] as f64];
                                     color += Vec3::from(diffuse).component_mul(&light_color) * cos;
@@ -76,8 +80,7 @@ impl PathTracerShader {
                 Light::Area(area_light) => {
                     if let Some(diffuse) = brdf.diffuse {
                         if !diffuse.is_zero() {
-                            let mut rng = rand::thread_rng();
-                            let rnd = Vec2::new(rng.gen(), rng.gen());
+                            let rnd = sampler.get_2d();
 
                             let SampleLightResult {
                                 color: light_color,
@@ -88,18 +91,19 @@ impl PathTracerShader {
                             let point = point.unwrap();
                             let _i_point = intersection.point();
 
-                            let mut light_dir = point - intersection.point();
-                            let light_distance = light_dir.norm();
-                            light_dir.normalize_mut();
+                            let light_dir = (point - intersection.point()).normalize();
 
-                            let cos_l = light_dir.dot(&intersection.shading_normal());
-                            let cos_l_la = light_dir.dot(&area_light.normal());
+                            let cos_l = light_dir.dot(intersection.shading_normal());
+                            let cos_l_la = light_dir.dot(area_light.normal());
 
                             if cos_l > 0.0 && cos_l_la <= 0.0 {
-                                let mut shadow = Ray::new(&intersection.point(), &light_dir);
-                                shadow.adjust_origin(intersection.geometric_normal());
-
-                                if scene.visibility(&shadow, light_distance - 0.0001) {
+                                let shadow = Ray::segment(
+                                    intersection.point(),
+                                    &point,
+                                    intersection.geometric_normal(),
+                                )
+                                .with_time(intersection.time());
+                                if !scene.occluded(&shadow) {
                                     let diffuse =
                                         [diffuse[0] as f64, diffuse[1] as f64, diffuse[2] as f64];
 
@@ -117,16 +121,16 @@ impl PathTracerShader {
         color
     }
 
-    fn diffuse_reflection<L: LightSampler>(
+    fn diffuse_reflection<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Intersection,
         material: &Brdf,
         scene: &Scene,
         depth: u32,
         light_sampler: &L,
+        sampler: &mut S,
     ) -> Color {
-        let mut rng = rand::thread_rng();
-        let randoms = [rng.gen::<f64>(), rng.gen::<f64>()];
+        let randoms = sampler.get_2d();
 
         let sqrt_rand1 = randoms[1].sqrt();
         let d_around = Vec3::new(
@@ -139,14 +143,13 @@ impl PathTracerShader {
         let pdf = cos_theta / PI;
 
         let gn = intersection.geometric_normal();
-        let (rx, ry) = gn.coordinate_system();
+        let sn = intersection.shading_normal();
+        let (rx, ry) = sn.coordinate_system();
 
-        let diffuse = Ray::new_with_adjusted_origin(
-            intersection.point(),
-            &d_around.rotate(&rx, &ry, &gn),
-            gn,
-        );
-        let d_intersection = scene.trace(&diffuse, light_sampler);
+        let diffuse =
+            Ray::new_with_adjusted_origin(intersection.point(), &d_around.rotate(&rx, &ry, sn), gn)
+                .with_time(intersection.time());
+        let d_intersection = scene.trace(&diffuse);
         if let Some(d_intersection) = d_intersection {
             if !d_intersection.is_light() {
                 let r_color = self.shade(
@@ -154,6 +157,7 @@ impl PathTracerShader {
                     scene,
                     Some(depth + 1),
                     light_sampler,
+                    sampler,
                 );
 
                 return (Color::from_column_slice(&[
@@ -168,28 +172,32 @@ impl PathTracerShader {
         Color::default()
     }
 
-    fn specular_reflection<L: LightSampler>(
+    fn specular_reflection<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Intersection,
         material: &Brdf,
         scene: &Scene,
         depth: u32,
         light_sampler: &L,
+        sampler: &mut S,
     ) -> Color {
         let gn = intersection.geometric_normal();
+        let sn = intersection.shading_normal();
         let wo = intersection.w_outgoing();
 
-        let cos = gn.dot(&wo);
+        let cos = sn.dot(wo);
 
-        let r_dir = 2.0 * cos * gn - wo;
-        let specular = Ray::new_with_adjusted_origin(intersection.point(), &r_dir, gn);
+        let r_dir = 2.0 * cos * sn - wo;
+        let specular = Ray::new_with_adjusted_origin(intersection.point(), &r_dir, gn)
+            .with_time(intersection.time());
 
-        let specular_intersection = scene.trace(&specular, light_sampler);
+        let specular_intersection = scene.trace(&specular);
         let r_color = self.shade(
             &specular_intersection,
             scene,
             Some(depth + 1),
             light_sampler,
+            sampler,
         );
 
         Vec3::from_column_slice(&[material[0] as f64, material[1] as f64, material[2] as f64])
@@ -198,21 +206,34 @@ impl PathTracerShader {
 }
 
 impl Shader for PathTracerShader {
-    fn shade<L: LightSampler>(
+    fn shade<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Option<Intersection>,
         scene: &Scene,
         depth: Option<u32>,
         light_sampler: &L,
+        sampler: &mut S,
     ) -> Color {
-        let mut color = Color::default();
+        self.shade_lighting(intersection, scene, depth, light_sampler, sampler)
+            .total()
+    }
+
+    fn shade_lighting<L: LightSampler, S: Sampler>(
+        &self,
+        intersection: &Option<Intersection>,
+        scene: &Scene,
+        depth: Option<u32>,
+        light_sampler: &L,
+        sampler: &mut S,
+    ) -> Lighting {
+        let mut lighting = Lighting::default();
         let Some(intersection) = intersection else {
-            return self.background;
+            return Lighting::direct(self.background);
         };
         let depth = depth.unwrap_or(0);
 
         if intersection.is_light() {
-            return intersection.light_intensity.unwrap();
+            return Lighting::direct(intersection.light_intensity.unwrap());
         }
 
         let material = intersection
@@ -223,9 +244,8 @@ impl Shader for PathTracerShader {
             .as_ref()
             .expect("Expected a material object in info");
 
-        let mut rng = rand::thread_rng();
-        let rnd_russian = rng.gen::<f64>();
-        if depth < MAX_DEPTH || rnd_russian < self.continue_p {
+        let rnd_russian = sampler.get_1d();
+        if depth < self.max_depth || rnd_russian < self.continue_p {
             let specular_a = material.specular.unwrap_or([0., 0., 0.]);
             let diffuse_a = material.diffuse.unwrap_or([0., 0., 0.]);
             let specular = Color::from_column_slice(&[
@@ -244,16 +264,28 @@ impl Shader for PathTracerShader {
             } else {
                 0.0
             };
-            let rnd = rng.gen::<f64>();
+            let rnd = sampler.get_1d();
 
             let l_color = if rnd <= s_p || s_p >= (1. - f64::EPSILON) {
-                self.specular_reflection(intersection, &specular_a, scene, depth, light_sampler)
-                    / s_p
+                self.specular_reflection(
+                    intersection,
+                    &specular_a,
+                    scene,
+                    depth,
+                    light_sampler,
+                    sampler,
+                ) / s_p
             } else {
-                self.diffuse_reflection(intersection, &diffuse_a, scene, depth, light_sampler)
-                    / (1. - s_p)
+                self.diffuse_reflection(
+                    intersection,
+                    &diffuse_a,
+                    scene,
+                    depth,
+                    light_sampler,
+                    sampler,
+                ) / (1. - s_p)
             };
-            color += if depth < MAX_DEPTH {
+            lighting.indirect += if depth < self.max_depth {
                 l_color
             } else {
                 l_color / self.continue_p
@@ -262,10 +294,10 @@ impl Shader for PathTracerShader {
 
         if let Some(diffuse) = material.diffuse {
             if !diffuse.is_zero() {
-                color += self.direct_lighting(intersection, material, scene);
+                lighting.direct += self.direct_lighting(intersection, material, scene, sampler);
             }
         }
 
-        color
+        lighting
     }
 }
@@ -4,38 +4,55 @@ use crate::helpers::{mul_vec3_with_rgb, Vec3, Zeroable};
 use crate::light::light_sampler::LightSampler;
 use crate::light::SampleLightResult;
 use crate::object::ray::Ray;
+use crate::sampler::Sampler;
 use crate::scene::Scene;
-use crate::{helpers::Color, light::Light, object::intersection::Intersection, shader::Shader};
+use crate::{
+    helpers::Color,
+    light::Light,
+    object::intersection::Intersection,
+    shader::{Lighting, Shader},
+};
 
 pub struct WhittedShader {
     background: Color,
+    max_depth: u32,
 }
 
 impl WhittedShader {
-    #[allow(dead_code)]
-    pub fn new(background: Color) -> Self {
-        Self { background }
+    pub fn new(background: Color, max_depth: u32) -> Self {
+        Self {
+            background,
+            max_depth,
+        }
     }
 
-    fn specular_reflection<L: LightSampler>(
+    fn specular_reflection<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Intersection,
         scene: &Scene,
         depth: u32,
         light_sampler: &L,
+        sampler: &mut S,
     ) -> Color {
         let wo = intersection.w_outgoing();
         let gn = intersection.geometric_normal();
+        let sn = intersection.shading_normal();
 
-        let cos = gn.dot(&wo);
+        let cos = sn.dot(wo);
 
-        let rdir = (2.0 * cos * gn) - wo;
-        let mut specular = Ray::new(&intersection.point(), &rdir);
+        let rdir = (2.0 * cos * sn) - wo;
+        let mut specular = Ray::new(intersection.point(), &rdir).with_time(intersection.time());
         specular.adjust_origin(gn);
 
-        let intersection = scene.trace(&specular, light_sampler);
+        let intersection = scene.trace(&specular);
 
-        self.shade(&intersection, scene, Some(depth + 1), light_sampler)
+        self.shade(
+            &intersection,
+            scene,
+            Some(depth + 1),
+            light_sampler,
+            sampler,
+        )
     }
 
     fn direct_lighting(
@@ -66,16 +83,18 @@ impl WhittedShader {
                                 ..
                             } = point_light.l();
                             let light_pos = light_pos.unwrap();
-                            let mut light_dir = light_pos - intersection.point();
-                            let light_distance = light_dir.norm();
-                            light_dir.normalize_mut();
+                            let light_dir = (light_pos - intersection.point()).normalize();
 
-                            let cos = light_dir.dot(&intersection.shading_normal());
+                            let cos = light_dir.dot(intersection.shading_normal());
 
                             if cos > 0.0 {
-                                let mut shadow = Ray::new(&intersection.point(), &light_dir);
-                                shadow.adjust_origin(intersection.geometric_normal());
-                                if scene.visibility(&shadow, light_distance) {
+                                let shadow = Ray::segment(
+                                    intersection.point(),
+                                    &light_pos,
+                                    intersection.geometric_normal(),
+                                )
+                                .with_time(intersection.time());
+                                if !scene.occluded(&shadow) {
                                     let diffuse =
                                         [diffuse[0] as f64, diffuse[1] as f64, diffuse[2] as f64];
                                     color +=
@@ -94,20 +113,37 @@ impl WhittedShader {
 }
 
 impl Shader for WhittedShader {
-    fn shade<L: LightSampler>(
+    fn shade<L: LightSampler, S: Sampler>(
         &self,
         intersection: &Option<Intersection>,
         scene: &Scene,
         depth: Option<u32>,
         light_sampler: &L,
+        sampler: &mut S,
     ) -> Color {
+        self.shade_lighting(intersection, scene, depth, light_sampler, sampler)
+            .total()
+    }
+
+    fn shade_lighting<L: LightSampler, S: Sampler>(
+        &self,
+        intersection: &Option<Intersection>,
+        scene: &Scene,
+        depth: Option<u32>,
+        light_sampler: &L,
+        sampler: &mut S,
+    ) -> Lighting {
         let depth = depth.unwrap_or(0);
-        let mut color = Color::new(0.0, 0.0, 0.0);
+        let mut lighting = Lighting::default();
 
         let Some(intersection) = intersection else {
-            return self.background;
+            return Lighting::direct(self.background);
         };
 
+        if intersection.is_light() {
+            return Lighting::direct(intersection.light_intensity.unwrap());
+        }
+
         let material = intersection
             .brdf
             .as_ref()
@@ -116,14 +152,20 @@ impl Shader for WhittedShader {
             .as_ref()
             .expect("Material in the material information");
 
-        color += self.direct_lighting(intersection, material, scene);
+        lighting.direct += self.direct_lighting(intersection, material, scene);
 
         if let Some(specular_material) = material.specular {
-            if !specular_material.is_zero() && depth < 3 {
-                color += self.specular_reflection(intersection, scene, depth + 1, light_sampler);
+            if !specular_material.is_zero() && depth < self.max_depth {
+                lighting.indirect += self.specular_reflection(
+                    intersection,
+                    scene,
+                    depth + 1,
+                    light_sampler,
+                    sampler,
+                );
             }
         }
 
-        color
+        lighting
     }
 }
@@ -0,0 +1,133 @@
+use std::{
+    cell::Cell,
+    fmt,
+    ops::{AddAssign, Sub},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+thread_local! {
+    static COUNTERS: Cell<RayCounts> = const { Cell::new(RayCounts::ZERO) };
+}
+
+/// Rays traced and BVH nodes visited. Every thread counts its own, and the renderer adds
+/// up the difference of each tile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct RayCounts {
+    pub primary: u64,
+    pub shadow: u64,
+    /// Reflected and refracted rays after the first hit.
+    pub secondary: u64,
+    pub bvh_node_visits: u64,
+}
+
+impl RayCounts {
+    const ZERO: Self = Self {
+        primary: 0,
+        shadow: 0,
+        secondary: 0,
+        bvh_node_visits: 0,
+    };
+
+    pub fn total(&self) -> u64 {
+        self.primary + self.shadow + self.secondary
+    }
+}
+
+impl Sub for RayCounts {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            primary: self.primary - other.primary,
+            shadow: self.shadow - other.shadow,
+            secondary: self.secondary - other.secondary,
+            bvh_node_visits: self.bvh_node_visits - other.bvh_node_visits,
+        }
+    }
+}
+
+impl AddAssign for RayCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.primary += other.primary;
+        self.shadow += other.shadow;
+        self.secondary += other.secondary;
+        self.bvh_node_visits += other.bvh_node_visits;
+    }
+}
+
+/// Updates the counters of the current thread.
+pub fn count(update: impl FnOnce(&mut RayCounts)) {
+    COUNTERS.with(|counters| {
+        let mut counts = counters.get();
+        update(&mut counts);
+        counters.set(counts);
+    });
+}
+
+/// Everything the current thread has counted so far.
+pub fn counts() -> RayCounts {
+    COUNTERS.with(Cell::get)
+}
+
+/// Seconds spent in each phase of a render.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Phases {
+    /// Reading the model, materials and textures.
+    pub load: f64,
+    /// Building meshes and acceleration structures.
+    pub build: f64,
+    pub render: f64,
+    pub denoise: f64,
+    pub write: f64,
+}
+
+/// Final report of a render, printed after it and optionally saved as JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct Statistics {
+    pub rays: RayCounts,
+    pub rays_per_second: f64,
+    /// Camera and secondary rays per camera path, so 1 when nothing is reflected.
+    pub average_path_length: f64,
+    pub phases: Phases,
+}
+
+impl Statistics {
+    pub fn new(rays: RayCounts, phases: Phases) -> Self {
+        Self {
+            rays,
+            rays_per_second: rays.total() as f64 / phases.render.max(f64::EPSILON),
+            average_path_length: (rays.primary + rays.secondary) as f64
+                / rays.primary.max(1) as f64,
+            phases,
+        }
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { rays, phases, .. } = self;
+        writeln!(f, "Primary rays:        {:>14}", rays.primary)?;
+        writeln!(f, "Shadow rays:         {:>14}", rays.shadow)?;
+        writeln!(f, "Secondary rays:      {:>14}", rays.secondary)?;
+        writeln!(f, "Rays per second:     {:>14.0}", self.rays_per_second)?;
+        writeln!(f, "Average path length: {:>14.3}", self.average_path_length)?;
+        writeln!(f, "BVH node visits:     {:>14}", rays.bvh_node_visits)?;
+        for (phase, seconds) in [
+            ("Load", phases.load),
+            ("Build", phases.build),
+            ("Render", phases.render),
+            ("Denoise", phases.denoise),
+            ("Write", phases.write),
+        ] {
+            writeln!(
+                f,
+                "{:<21}{:>14.3?}",
+                format!("{phase} time:"),
+                Duration::from_secs_f64(seconds)
+            )?;
+        }
+        Ok(())
+    }
+}
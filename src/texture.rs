@@ -0,0 +1,544 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tobj::Material;
+
+use crate::{
+    helpers::{Color, Rotateable, Vec2, Vec3},
+    object::intersection::Intersection,
+};
+
+#[derive(Debug)]
+pub struct Texture {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+}
+
+impl Texture {
+    /// Loads an image as linear values; color maps are stored sRGB-encoded and decoded here,
+    /// while data maps (normals, heights) are read as they are.
+    pub fn load(path: &Path, srgb: bool) -> image::ImageResult<Self> {
+        let image = image::open(path)?.into_rgb32f();
+        let decode = |value: f32| {
+            let value = value as f64;
+            if srgb {
+                srgb_to_linear(value)
+            } else {
+                value
+            }
+        };
+
+        Ok(Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            texels: image
+                .pixels()
+                .map(|pixel| Color::new(decode(pixel[0]), decode(pixel[1]), decode(pixel[2])))
+                .collect(),
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.texels[y * self.width + x]
+    }
+
+    /// Bilinearly filtered lookup with repeat wrapping. `v` grows upwards as in OBJ files.
+    pub fn bilinear(&self, uv: &Vec2) -> Color {
+        let x = uv.x * self.width as f64 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        self.texel(x0, y0) * (1.0 - dx) * (1.0 - dy)
+            + self.texel(x0 + 1, y0) * dx * (1.0 - dy)
+            + self.texel(x0, y0 + 1) * (1.0 - dx) * dy
+            + self.texel(x0 + 1, y0 + 1) * dx * dy
+    }
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// A texture as referenced by an MTL statement, with the options that apply to it.
+#[derive(Debug, Clone)]
+pub struct TextureMap {
+    texture: Arc<Texture>,
+    offset: Vec2,
+    scale: Vec2,
+    bump_multiplier: f64,
+}
+
+impl TextureMap {
+    pub fn lookup(&self, uv: &Vec2) -> Color {
+        self.texture.bilinear(&self.transform(uv))
+    }
+
+    pub fn bump_multiplier(&self) -> f64 {
+        self.bump_multiplier
+    }
+
+    /// Luminance of the map scaled by `-bm`, as used by height-based bump maps.
+    pub fn height(&self, uv: &Vec2) -> f64 {
+        let texel = self.lookup(uv);
+        (0.2126 * texel.x + 0.7152 * texel.y + 0.0722 * texel.z) * self.bump_multiplier
+    }
+
+    /// Forward differences of [`Self::height`] over one texel in `u` and in `v`.
+    pub fn height_gradient(&self, uv: &Vec2) -> Vec2 {
+        let du = 1.0 / (self.texture.width() as f64 * self.scale.x);
+        let dv = 1.0 / (self.texture.height() as f64 * self.scale.y);
+        let height = self.height(uv);
+
+        Vec2::new(
+            (self.height(&Vec2::new(uv.x + du, uv.y)) - height) / du,
+            (self.height(&Vec2::new(uv.x, uv.y + dv)) - height) / dv,
+        )
+    }
+
+    fn transform(&self, uv: &Vec2) -> Vec2 {
+        uv.component_mul(&self.scale) + self.offset
+    }
+}
+
+/// File name and options of an MTL texture statement such as `-o 0.1 -bm 2 bump map.png`.
+/// Options that do not affect lookups are skipped together with their arguments.
+#[derive(Debug, PartialEq)]
+struct TextureStatement {
+    file_name: String,
+    offset: Vec2,
+    scale: Vec2,
+    bump_multiplier: f64,
+}
+
+impl TextureStatement {
+    fn parse(statement: &str) -> Option<Self> {
+        let mut parsed = Self {
+            file_name: String::new(),
+            offset: Vec2::zeros(),
+            scale: Vec2::new(1.0, 1.0),
+            bump_multiplier: 1.0,
+        };
+        let mut file_name = Vec::new();
+        let mut words = statement.split_whitespace().peekable();
+
+        while let Some(word) = words.next() {
+            match word {
+                "-o" | "-s" | "-t" => {
+                    let mut values = Vec::new();
+                    while values.len() < 3 {
+                        match words.peek().and_then(|value| value.parse::<f64>().ok()) {
+                            Some(value) => {
+                                values.push(value);
+                                words.next();
+                            }
+                            None => break,
+                        }
+                    }
+                    let value = Vec2::new(
+                        values.first().copied().unwrap_or(0.0),
+                        values.get(1).copied().unwrap_or(0.0),
+                    );
+                    match word {
+                        "-o" => parsed.offset = value,
+                        "-s" => {
+                            parsed.scale = Vec2::new(
+                                values.first().copied().unwrap_or(1.0),
+                                values.get(1).copied().unwrap_or(1.0),
+                            )
+                        }
+                        _ => {}
+                    }
+                }
+                "-bm" => {
+                    parsed.bump_multiplier = words.next()?.parse().ok()?;
+                }
+                "-mm" => {
+                    words.next();
+                    words.next();
+                }
+                "-blendu" | "-blendv" | "-boost" | "-cc" | "-clamp" | "-imfchan" | "-texres"
+                | "-type" | "-colorspace" => {
+                    words.next();
+                }
+                _ => file_name.push(word),
+            }
+        }
+
+        if file_name.is_empty() {
+            return None;
+        }
+        parsed.file_name = file_name.join(" ");
+        Some(parsed)
+    }
+}
+
+/// Loads every texture file once, resolving names against the directories of the MTL
+/// libraries referenced by the OBJ file.
+pub struct TextureCache {
+    libraries: Vec<PathBuf>,
+    search_directories: Vec<PathBuf>,
+    textures: HashMap<(PathBuf, bool), Option<Arc<Texture>>>,
+    /// Why textures could not be loaded, once for every file.
+    warnings: Vec<String>,
+}
+
+impl TextureCache {
+    pub fn new(obj_path: &str) -> Self {
+        let obj_directory = Path::new(obj_path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        let libraries: Vec<PathBuf> = Self::mtl_libraries(obj_path)
+            .iter()
+            .map(|library| obj_directory.join(library))
+            .collect();
+        let mut search_directories: Vec<PathBuf> = libraries
+            .iter()
+            .filter_map(|library| library.parent().map(Path::to_path_buf))
+            .collect();
+        search_directories.push(obj_directory);
+        search_directories.dedup();
+
+        Self {
+            libraries,
+            search_directories,
+            textures: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// MTL libraries and texture files the materials were loaded from, in a fixed order,
+    /// whether they exist or not.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut textures: Vec<PathBuf> =
+            self.textures.keys().map(|(path, _)| path.clone()).collect();
+        textures.sort();
+        textures.dedup();
+        self.libraries.iter().cloned().chain(textures).collect()
+    }
+
+    pub fn into_warnings(self) -> Vec<String> {
+        self.warnings
+    }
+
+    fn mtl_libraries(obj_path: &str) -> Vec<String> {
+        let Ok(file) = File::open(obj_path) else {
+            return Vec::new();
+        };
+
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| {
+                line.trim_start()
+                    .strip_prefix("mtllib ")
+                    .map(mtl_library_names)
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Texture map for an MTL statement, or `None` with a warning when the file cannot be
+    /// found or decoded.
+    pub fn load(&mut self, statement: &str, srgb: bool) -> Option<TextureMap> {
+        let statement = TextureStatement::parse(statement)?;
+        let path = self
+            .search_directories
+            .iter()
+            .map(|directory| directory.join(&statement.file_name))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| PathBuf::from(&statement.file_name));
+
+        let warnings = &mut self.warnings;
+        let texture = self
+            .textures
+            .entry((path.clone(), srgb))
+            .or_insert_with(|| match Texture::load(&path, srgb) {
+                Ok(texture) => Some(Arc::new(texture)),
+                Err(error) => {
+                    warnings.push(format!(
+                        "could not load texture {}: {error}",
+                        path.display()
+                    ));
+                    None
+                }
+            })
+            .clone()?;
+
+        Some(TextureMap {
+            texture,
+            offset: statement.offset,
+            scale: statement.scale,
+            bump_multiplier: statement.bump_multiplier,
+        })
+    }
+}
+
+/// File names of an `mtllib` statement, which are separated by whitespace unless it is
+/// escaped with a backslash.
+fn mtl_library_names(libraries: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut name = String::new();
+    let mut characters = libraries.chars().peekable();
+
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' if characters.peek().is_some_and(|next| next.is_whitespace()) => {
+                name.extend(characters.next());
+            }
+            character if character.is_whitespace() => {
+                if !name.is_empty() {
+                    names.push(std::mem::take(&mut name));
+                }
+            }
+            character => name.push(character),
+        }
+    }
+    if !name.is_empty() {
+        names.push(name);
+    }
+    names
+}
+
+/// Texture maps attached to one MTL material.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialTextures {
+    pub diffuse: Option<TextureMap>,
+    /// Height map from `bump` / `map_Bump`, which tobj reports as `normal_texture`.
+    pub bump: Option<TextureMap>,
+    /// Tangent-space normal map from `norm`.
+    pub normal: Option<TextureMap>,
+}
+
+impl MaterialTextures {
+    pub fn load(material: &Material, cache: &mut TextureCache) -> Self {
+        Self {
+            diffuse: material
+                .diffuse_texture
+                .as_deref()
+                .and_then(|statement| cache.load(statement, true)),
+            bump: material
+                .normal_texture
+                .as_deref()
+                .and_then(|statement| cache.load(statement, false)),
+            normal: material
+                .unknown_param
+                .get("norm")
+                .and_then(|statement| cache.load(statement, false)),
+        }
+    }
+
+    /// Perturbs the shading normal of a textured hit with the normal map, or with the
+    /// bump map when there is no normal map.
+    pub fn perturb_shading_normal(&self, intersection: &mut Intersection) {
+        let Some(uv) = intersection.uv().copied() else {
+            return;
+        };
+        let normal = *intersection.shading_normal();
+        let (dpdu, dpdv) = (*intersection.dpdu(), *intersection.dpdv());
+
+        let perturbed = if let Some(normal_map) = &self.normal {
+            let texel = normal_map.lookup(&uv) * 2.0 - Vec3::new(1.0, 1.0, 1.0);
+            let bump_multiplier = normal_map.bump_multiplier();
+
+            let tangent = (dpdu - normal * normal.dot(&dpdu)).try_normalize(f64::EPSILON);
+            let Some(tangent) = tangent else {
+                return;
+            };
+            let mut bitangent = normal.cross(&tangent);
+            if bitangent.dot(&dpdv) < 0.0 {
+                bitangent = -bitangent;
+            }
+
+            texel.x * bump_multiplier * tangent
+                + texel.y * bump_multiplier * bitangent
+                + texel.z * normal
+        } else if let Some(bump_map) = &self.bump {
+            let gradient = bump_map.height_gradient(&uv);
+            (dpdu + gradient.x * normal).cross(&(dpdv + gradient.y * normal))
+        } else {
+            return;
+        };
+
+        if let Some(perturbed) = perturbed.try_normalize(f64::EPSILON) {
+            intersection.set_shading_normal(perturbed.face_forward(&normal));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object::{face::FaceBuilder, intersection::Intersectable, ray::Ray};
+
+    use super::*;
+
+    #[test]
+    fn bilinear_lookups_blend_the_nearest_texels() {
+        // Texel centers of the top row are at v = 0.75, those of the bottom row at 0.25.
+        let texture = Arc::new(Texture {
+            width: 2,
+            height: 2,
+            texels: [0.0, 1.0, 2.0, 3.0].map(Color::repeat).to_vec(),
+        });
+        let lookup = |u, v| texture.bilinear(&Vec2::new(u, v)).x;
+
+        assert_eq!(lookup(0.25, 0.75), 0.0);
+        assert_eq!(lookup(0.75, 0.25), 3.0);
+        assert_eq!(lookup(0.5, 0.5), 1.5);
+        // Halfway between the left and, wrapping around, the right texel of the top row.
+        assert_eq!(lookup(0.0, 0.75), 0.5);
+        assert_eq!(lookup(1.25, 1.75), 0.0);
+
+        let map = TextureMap {
+            texture,
+            offset: Vec2::new(0.5, 0.0),
+            scale: Vec2::new(0.5, 1.0),
+            bump_multiplier: 1.0,
+        };
+        assert_eq!(map.lookup(&Vec2::new(0.5, 0.75)).x, 1.0);
+    }
+
+    #[test]
+    fn statements_give_the_file_name_and_lookup_options() {
+        let parsed = TextureStatement::parse("-o 0.1 -clamp on diffuse.jpg").unwrap();
+        assert_eq!(parsed.file_name, "diffuse.jpg");
+        assert_eq!(parsed.offset, Vec2::new(0.1, 0.0));
+        assert_eq!(parsed.scale, Vec2::new(1.0, 1.0));
+
+        let parsed = TextureStatement::parse("-s 0.1 0.2 -type sphere texture 01.png").unwrap();
+        assert_eq!(parsed.file_name, "texture 01.png");
+        assert_eq!(parsed.scale, Vec2::new(0.1, 0.2));
+        assert_eq!(parsed.offset, Vec2::zeros());
+        assert_eq!(TextureStatement::parse("-t 0.1 0.2 0.3"), None);
+
+        assert_eq!(
+            mtl_library_names("a.mtl  with\\ spaces.mtl\tb.mtl "),
+            ["a.mtl", "with spaces.mtl", "b.mtl"]
+        );
+        // Missing files are reported once, however often they are used.
+        let mut cache = TextureCache::new("models/texture-options-issue-85.obj");
+        assert!(cache.load("-o 0.1 diffuse.jpg", true).is_none());
+        assert!(cache.load("diffuse.jpg", true).is_none());
+        let warnings = cache.into_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("diffuse.jpg"));
+    }
+
+    /// Copies `model` and its MTL library next to generated texture images.
+    fn textured_copy(model: &str, images: &[(&str, image::RgbImage)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("raytracer-{}-{model}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for extension in ["obj", "mtl"] {
+            let file_name = format!("{model}.{extension}");
+            std::fs::copy(
+                Path::new("models").join(&file_name),
+                directory.join(&file_name),
+            )
+            .unwrap();
+        }
+        for (file_name, image) in images {
+            image.save(directory.join(file_name)).unwrap();
+        }
+        directory.join(format!("{model}.obj"))
+    }
+
+    #[test]
+    fn bump_and_normal_maps_tilt_the_shading_normal() {
+        let parsed = TextureStatement::parse("-imfchan r bumpmap2.jpg -bm 1.5").unwrap();
+        assert_eq!(parsed.file_name, "bumpmap2.jpg");
+        assert_eq!(parsed.bump_multiplier, 1.5);
+        assert_eq!(TextureStatement::parse("bump.jpg -bm"), None);
+
+        let textured_hit = |textures: &MaterialTextures, vertices: [Vec3; 3]| {
+            let face = FaceBuilder::new(vertices)
+                .texcoords([
+                    Vec2::new(0.1, 0.2),
+                    Vec2::new(0.9, 0.3),
+                    Vec2::new(0.4, 0.8),
+                ])
+                .build();
+            let centroid = (vertices[0] + vertices[1] + vertices[2]) / 3.0;
+            let origin = centroid + face.normal();
+            let mut intersection = face.intersect(&Ray::new(&origin, &-face.normal())).unwrap();
+            let normal = *intersection.shading_normal();
+            textures.perturb_shading_normal(&mut intersection);
+            (normal, intersection)
+        };
+        let first_triangle = |model: &tobj::Model| {
+            let position = |index: u32| {
+                let index = index as usize * 3;
+                let position = &model.mesh.positions[index..index + 3];
+                Vec3::new(position[0] as f64, position[1] as f64, position[2] as f64)
+            };
+            let indices = &model.mesh.indices;
+            [
+                position(indices[0]),
+                position(indices[1]),
+                position(indices[2]),
+            ]
+        };
+
+        // A height map rising along u tilts the normal against u; a flat one leaves it.
+        for (value, tilted) in [(None, true), (Some(128), false)] {
+            let bump = image::RgbImage::from_fn(64, 64, |x, _| {
+                image::Rgb([value.unwrap_or(x as u8 * 4); 3])
+            });
+            let obj_path = textured_copy("map-bump", &[("bump.jpg", bump)]);
+            let obj_path = obj_path.to_str().unwrap();
+            let (models, materials) = tobj::load_obj(obj_path, &tobj::GPU_LOAD_OPTIONS).unwrap();
+            let mut cache = TextureCache::new(obj_path);
+            let textures = MaterialTextures::load(&materials.unwrap()[0], &mut cache);
+            std::fs::remove_dir_all(Path::new(obj_path).parent().unwrap()).unwrap();
+            assert!(cache.into_warnings().is_empty());
+            let model = models
+                .iter()
+                .find(|model| model.mesh.material_id == Some(0))
+                .unwrap();
+
+            let (normal, intersection) = textured_hit(&textures, first_triangle(model));
+            let perturbed = intersection.shading_normal();
+            let along_u = perturbed.dot(&intersection.dpdu().normalize());
+            assert!((perturbed.norm() - 1.0).abs() < 1e-9 && perturbed.dot(&normal) > 0.0);
+            assert_eq!(along_u.abs() > 0.1, tilted, "{along_u}");
+        }
+
+        // (0.75, 0.5, 1) decodes to (0.5, 0, 1), with x scaled by `-bm 3`.
+        let normal_map = image::RgbImage::from_pixel(8, 8, image::Rgb([191, 128, 255]));
+        let obj_path = textured_copy("norm-texopt", &[("normalmap.jpg", normal_map)]);
+        let obj_path = obj_path.to_str().unwrap();
+        let (models, materials) = tobj::load_obj(obj_path, &tobj::GPU_LOAD_OPTIONS).unwrap();
+        let textures =
+            MaterialTextures::load(&materials.unwrap()[0], &mut TextureCache::new(obj_path));
+        std::fs::remove_dir_all(Path::new(obj_path).parent().unwrap()).unwrap();
+        assert_eq!(textures.normal.as_ref().unwrap().bump_multiplier(), 3.0);
+        let (normal, intersection) = textured_hit(&textures, first_triangle(&models[0]));
+        let tangent = intersection.dpdu().normalize();
+        let expected = (1.5 * tangent + normal).normalize();
+        // Within what JPEG compression leaves of the colors.
+        assert!((intersection.shading_normal() - expected).norm() < 0.05);
+    }
+}